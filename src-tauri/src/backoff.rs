@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// Exponential backoff for WebSocket reconnect attempts.
+//
+// Each failed attempt multiplies the delay by `multiplier` (starting from `initial_delay`), up to
+// `max_delay`. A random jitter of +/- `jitter` (a fraction of the delay) is applied so a Vibin
+// host coming back online isn't hit by every client at exactly the same moment.
+//
+// The policy is persisted in the application's store (see main.rs) under RECONNECT_POLICY_KEY,
+// with the delays in milliseconds. Missing fields take their default values.
+
+pub const RECONNECT_POLICY_KEY: &str = "reconnect-policy";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    #[serde(rename = "initial_delay_ms", with = "millis")]
+    pub initial_delay: Duration,
+    pub multiplier: f64,
+    #[serde(rename = "max_delay_ms", with = "millis")]
+    pub max_delay: Duration,
+    pub jitter: f64,
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(60),
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Backoff { policy, attempt: 0 }
+    }
+
+    /// Number of reconnect attempts made since the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Start again from `initial_delay`. Called after a successful connection.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Get the delay to wait before the next attempt, or None if `max_attempts` has been reached.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempt >= max_attempts {
+                return None;
+            }
+        }
+
+        let base = self.base_delay(self.attempt);
+        self.attempt += 1;
+
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+
+        if jitter == 0.0 {
+            return Some(base);
+        }
+
+        let factor = rand::thread_rng().gen_range((1.0 - jitter)..=(1.0 + jitter));
+
+        Some(base.mul_f64(factor).min(self.policy.max_delay))
+    }
+
+    fn base_delay(&self, attempt: u32) -> Duration {
+        let max_secs = self.policy.max_delay.as_secs_f64();
+        let secs = self.policy.initial_delay.as_secs_f64()
+            * self.policy.multiplier.max(1.0).powi(attempt.min(i32::MAX as u32) as i32);
+
+        Duration::from_secs_f64(if secs.is_finite() { secs.min(max_secs) } else { max_secs })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::backoff::{Backoff, ReconnectPolicy};
+
+    fn policy_without_jitter() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(10),
+            jitter: 0.0,
+            max_attempts: None,
+        }
+    }
+
+    #[test]
+    fn it_grows_exponentially_up_to_max_delay() {
+        let mut backoff = Backoff::new(policy_without_jitter());
+
+        let delays: Vec<u64> = (0..6).map(|_| backoff.next_delay().unwrap().as_secs()).collect();
+
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+    }
+
+    #[test]
+    fn it_resets_to_initial_delay() {
+        let mut backoff = Backoff::new(policy_without_jitter());
+
+        backoff.next_delay();
+        backoff.next_delay();
+        assert_eq!(backoff.attempt(), 2);
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn it_stops_after_max_attempts() {
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            max_attempts: Some(2),
+            ..policy_without_jitter()
+        });
+
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_none());
+    }
+
+    #[test]
+    fn it_applies_jitter_within_bounds() {
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            jitter: 0.5,
+            ..policy_without_jitter()
+        });
+
+        for _ in 0..20 {
+            backoff.reset();
+            let delay = backoff.next_delay().unwrap().as_secs_f64();
+
+            assert!((0.5..=1.5).contains(&delay), "{delay}");
+        }
+    }
+
+    #[test]
+    fn it_round_trips_through_json_with_defaults_for_missing_fields() {
+        let policy: ReconnectPolicy =
+            serde_json::from_value(serde_json::json!({ "max_delay_ms": 30000, "max_attempts": 5 }))
+                .unwrap();
+
+        assert_eq!(
+            policy,
+            ReconnectPolicy {
+                max_delay: Duration::from_secs(30),
+                max_attempts: Some(5),
+                ..ReconnectPolicy::default()
+            }
+        );
+
+        let json = serde_json::to_value(&policy).unwrap();
+        assert_eq!(json["initial_delay_ms"], 1000);
+        assert_eq!(serde_json::from_value::<ReconnectPolicy>(json).unwrap(), policy);
+    }
+}
@@ -0,0 +1,871 @@
+// A mock Vibin server, for developing weevibin without a StreamMagic streamer and Vibin instance.
+//
+// Serves a WebSocket endpoint (/ws) which emits System, TransportState, CurrentlyPlaying, Queue
+// and Position messages for a small simulated play queue; and the REST command endpoints (/api/...)
+// used by weevibin. Commands mutate the simulated state, and the resulting state changes are
+// announced to all connected WebSocket clients.
+//
+// Usage:
+//
+//   cargo run --bin mock_vibin -- [OPTIONS]
+//
+//   --host <HOST>            Address to listen on (default: 127.0.0.1). The mock's endpoints
+//                            aren't authenticated, so only use 0.0.0.0 on a trusted network.
+//   --port <PORT>            Port to listen on (default: 8080)
+//   --ping-interval <SECS>   Seconds between WebSocket pings (default: 5)
+//   --close-after <SECS>     Send a Close to each client this long after it connects
+//   --drop-after <SECS>      Abruptly drop each client connection this long after it connects
+//   --stall-after <SECS>     Stop sending anything (including pings) this long after connecting
+//
+// Point weevibin at the mock with a Vibin host of "localhost:<PORT>".
+
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant, interval, sleep_until};
+use tokio_tungstenite::accept_async;
+use tungstenite::Message;
+
+// ------------------------------------------------------------------------------------------------
+// Configuration
+
+struct MockConfig {
+    host: String,
+    port: u16,
+    ping_interval: Duration,
+    close_after: Option<Duration>,
+    drop_after: Option<Duration>,
+    stall_after: Option<Duration>,
+}
+
+impl MockConfig {
+    fn from_args() -> Result<Self, String> {
+        let mut config = MockConfig {
+            host: String::from("127.0.0.1"),
+            port: 8080,
+            ping_interval: Duration::from_secs(5),
+            close_after: None,
+            drop_after: None,
+            stall_after: None,
+        };
+
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+
+            match arg.as_str() {
+                "--host" => config.host = value()?,
+                "--port" => config.port = parse_arg(&arg, &value()?)?,
+                "--ping-interval" => config.ping_interval = parse_secs(&arg, &value()?)?,
+                "--close-after" => config.close_after = Some(parse_secs(&arg, &value()?)?),
+                "--drop-after" => config.drop_after = Some(parse_secs(&arg, &value()?)?),
+                "--stall-after" => config.stall_after = Some(parse_secs(&arg, &value()?)?),
+                unknown => return Err(format!("Unknown argument: {unknown}")),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("Invalid value for {arg}: {value}"))
+}
+
+fn parse_secs(arg: &str, value: &str) -> Result<Duration, String> {
+    parse_arg::<f64>(arg, value).map(Duration::from_secs_f64)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Simulated streamer state
+
+struct MockTrack {
+    title: &'static str,
+    artist: &'static str,
+    album: &'static str,
+    duration: isize,
+    codec: &'static str,
+    sample_rate: isize,
+    bit_depth: isize,
+}
+
+const TRACKS: [MockTrack; 3] = [
+    MockTrack {
+        title: "So What",
+        artist: "Miles Davis",
+        album: "Kind of Blue",
+        duration: 562,
+        codec: "FLAC",
+        sample_rate: 44100,
+        bit_depth: 16,
+    },
+    MockTrack {
+        title: "Teardrop",
+        artist: "Massive Attack",
+        album: "Mezzanine",
+        duration: 330,
+        codec: "FLAC",
+        sample_rate: 96000,
+        bit_depth: 24,
+    },
+    MockTrack {
+        title: "Windowlicker",
+        artist: "Aphex Twin",
+        album: "Windowlicker",
+        duration: 367,
+        codec: "MP3",
+        sample_rate: 44100,
+        bit_depth: 16,
+    },
+];
+
+struct MockSource {
+    id: &'static str,
+    name: &'static str,
+    class: &'static str,
+}
+
+const SOURCES: [MockSource; 3] = [
+    MockSource { id: "MEDIA_PLAYER", name: "Media Library", class: "stream.media" },
+    MockSource { id: "AIRPLAY", name: "AirPlay", class: "stream.service.airplay" },
+    MockSource { id: "IR", name: "Internet Radio", class: "stream.radio" },
+];
+
+struct MockState {
+    power: bool,
+    play_state: &'static str,
+    repeat: bool,
+    shuffle: bool,
+    mute: bool,
+    volume: f32,
+    /// Indexes into TRACKS. Each track's queue entry id is its TRACKS index plus one.
+    queue: Vec<usize>,
+    queue_position: usize,
+    position: isize,
+    source_index: usize,
+}
+
+impl MockState {
+    fn new() -> Self {
+        MockState {
+            power: true,
+            play_state: "play",
+            repeat: false,
+            shuffle: false,
+            mute: false,
+            volume: 0.35,
+            queue: (0..TRACKS.len()).collect(),
+            queue_position: 0,
+            position: 0,
+            source_index: 0,
+        }
+    }
+
+    fn track_index(&self) -> usize {
+        self.queue[self.queue_position]
+    }
+
+    fn track(&self) -> &'static MockTrack {
+        &TRACKS[self.track_index()]
+    }
+
+    fn source(&self) -> &'static MockSource {
+        &SOURCES[self.source_index]
+    }
+
+    fn change_track(&mut self, forward: bool) {
+        let queue_length = self.queue.len();
+
+        self.queue_position = match forward {
+            true => (self.queue_position + 1) % queue_length,
+            false => (self.queue_position + queue_length - 1) % queue_length,
+        };
+        self.position = 0;
+    }
+
+    fn queue_position_of(&self, entry_id: &str) -> Option<usize> {
+        let entry_id = entry_id.parse::<usize>().ok()?;
+
+        self.queue.iter().position(|track_index| track_index + 1 == entry_id)
+    }
+
+    fn active_controls(&self) -> Vec<&'static str> {
+        let mut controls = vec!["next", "previous", "repeat", "shuffle", "seek", "toggle_playback"];
+
+        controls.push(if self.play_state == "play" { "pause" } else { "play" });
+        controls
+    }
+}
+
+type MockStateMutex = Arc<Mutex<MockState>>;
+
+// ------------------------------------------------------------------------------------------------
+// Vibin messages
+
+fn on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+fn vibin_message(msg_type: &str, payload: Value) -> String {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+
+    json!({
+        "id": format!("mock-{time}"),
+        "client_id": "mock-vibin",
+        "time": time,
+        "type": msg_type,
+        "payload": payload,
+    })
+    .to_string()
+}
+
+fn system_message(state: &MockState) -> String {
+    let track = state.track();
+    let source = state.source();
+
+    let sources: Vec<Value> = SOURCES
+        .iter()
+        .enumerate()
+        .map(|(index, source)| {
+            json!({
+                "id": source.id,
+                "name": source.name,
+                "default_name": source.name,
+                "class": source.class,
+                "nameable": false,
+                "ui_selectable": true,
+                "description": source.name,
+                "description_locale": source.name,
+                "preferred_order": index,
+            })
+        })
+        .collect();
+
+    vibin_message("System", json!({
+        "power": on_off(state.power),
+        "streamer": {
+            "name": "Mock Streamer",
+            "power": on_off(state.power),
+            "sources": {
+                "active": sources[state.source_index],
+                "available": sources,
+            },
+            "display": {
+                "line1": track.title,
+                "line2": track.artist,
+                "line3": track.album,
+                "format": format!("{} {}/{}", track.codec, track.bit_depth, track.sample_rate / 1000),
+                "playback_source": source.name,
+                "art_url": Value::Null,
+            },
+        },
+        "amplifier": {
+            "name": "Mock Amplifier",
+            "power": on_off(state.power),
+            "mute": on_off(state.mute),
+            "volume": state.volume,
+        },
+    }))
+}
+
+fn transport_message(state: &MockState) -> String {
+    vibin_message("TransportState", json!({
+        "play_state": state.play_state,
+        "active_controls": state.active_controls(),
+        "repeat": if state.repeat { "all" } else { "off" },
+        "shuffle": if state.shuffle { "all" } else { "off" },
+    }))
+}
+
+fn currently_playing_message(state: &MockState) -> String {
+    let track = state.track();
+
+    vibin_message("CurrentlyPlaying", json!({
+        "album_media_id": format!("mock-album-{}", state.track_index()),
+        "track_media_id": format!("mock-track-{}", state.track_index()),
+        "active_track": {
+            "title": track.title,
+            "artist": track.artist,
+            "album": track.album,
+            "art_url": Value::Null,
+            "duration": track.duration,
+        },
+        "format": {
+            "sample_format": "pcm",
+            "mqa": "none",
+            "codec": track.codec,
+            "lossless": track.codec == "FLAC",
+            "sample_rate": track.sample_rate,
+            "bit_depth": track.bit_depth,
+            "encoding": track.codec.to_lowercase(),
+        },
+        "stream": {
+            "url": format!("http://localhost/mock/{}.{}", state.track_index(), track.codec.to_lowercase()),
+        },
+    }))
+}
+
+fn queue_message(state: &MockState) -> String {
+    let items: Vec<Value> = state
+        .queue
+        .iter()
+        .enumerate()
+        .map(|(position, track_index)| {
+            let track = &TRACKS[*track_index];
+
+            json!({
+                "id": track_index + 1,
+                "position": position,
+                "albumArtURI": Value::Null,
+                "metadata": {
+                    "title": track.title,
+                    "artist": track.artist,
+                    "album": track.album,
+                    "duration": track.duration,
+                },
+            })
+        })
+        .collect();
+
+    vibin_message("Queue", json!({
+        "play_id": state.track_index() + 1,
+        "play_position": state.queue_position,
+        "items": items,
+    }))
+}
+
+fn position_message(state: &MockState) -> String {
+    vibin_message("Position", json!({ "position": state.position }))
+}
+
+/// All the messages a newly-connected client needs to know the full state.
+fn full_state_messages(state: &MockState) -> Vec<String> {
+    vec![
+        system_message(state),
+        transport_message(state),
+        currently_playing_message(state),
+        queue_message(state),
+        position_message(state),
+    ]
+}
+
+// ------------------------------------------------------------------------------------------------
+// Playback simulation
+
+/// Advance the playhead once per second while playing, moving to the next track at the end of
+/// the current one.
+async fn run_playback(state_mutex: MockStateMutex, updates: broadcast::Sender<String>) {
+    let mut ticker = interval(Duration::from_secs(1));
+
+    loop {
+        ticker.tick().await;
+
+        let messages = {
+            let mut state = state_mutex.lock().unwrap();
+
+            if !state.power || state.play_state != "play" {
+                continue;
+            }
+
+            state.position += 1;
+
+            if state.position >= state.track().duration {
+                state.change_track(true);
+                vec![
+                    currently_playing_message(&state),
+                    system_message(&state),
+                    queue_message(&state),
+                    position_message(&state),
+                ]
+            } else {
+                vec![position_message(&state)]
+            }
+        };
+
+        for message in messages {
+            let _ = updates.send(message);
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// REST command handling
+
+struct CommandResponse {
+    status: u16,
+    body: Value,
+}
+
+impl CommandResponse {
+    fn ok() -> Self {
+        CommandResponse { status: 200, body: json!({}) }
+    }
+
+    fn error(status: u16, detail: &str) -> Self {
+        CommandResponse { status, body: json!({ "detail": detail }) }
+    }
+}
+
+/// Apply a REST command to the simulated state, returning the response and the messages to send
+/// to WebSocket clients as a result.
+fn apply_command(
+    state: &mut MockState,
+    method: &str,
+    path: &str,
+    query: &HashMap<String, String>,
+) -> (CommandResponse, Vec<String>) {
+    if method != "POST" {
+        return (CommandResponse::error(405, "Method not allowed"), vec![]);
+    }
+
+    let Some(endpoint) = path.strip_prefix("/api") else {
+        return (CommandResponse::error(404, "Not found"), vec![]);
+    };
+
+    let segments: Vec<String> = endpoint.trim_matches('/').split('/').map(percent_decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match segments.as_slice() {
+        ["system", "power", action] => {
+            state.power = match *action {
+                "on" => true,
+                "off" => false,
+                "toggle" => !state.power,
+                _ => return (CommandResponse::error(404, "Unknown power action"), vec![]),
+            };
+
+            (CommandResponse::ok(), vec![system_message(state)])
+        }
+        ["system", "streamer", "audio_source", name] => {
+            match SOURCES.iter().position(|source| source.name == *name || source.id == *name) {
+                Some(index) => {
+                    state.source_index = index;
+                    (CommandResponse::ok(), vec![system_message(state)])
+                }
+                None => (CommandResponse::error(404, "Unknown audio source"), vec![]),
+            }
+        }
+        ["system", "amplifier", "mute", "toggle"] => {
+            state.mute = !state.mute;
+            (CommandResponse::ok(), vec![system_message(state)])
+        }
+        ["system", "amplifier", "volume", level] => {
+            state.volume = match *level {
+                "up" => (state.volume + 0.01).min(1.0),
+                "down" => (state.volume - 0.01).max(0.0),
+                level => match level.parse::<f32>() {
+                    Ok(level) if (0.0..=1.0).contains(&level) => level,
+                    _ => return (CommandResponse::error(400, "Invalid volume level"), vec![]),
+                },
+            };
+
+            (CommandResponse::ok(), vec![system_message(state)])
+        }
+        ["transport", "seek"] => {
+            let target = query.get("target").and_then(|target| target.parse::<f64>().ok());
+
+            match target {
+                Some(target) if target >= 0.0 => {
+                    // Vibin accepts either seconds, or a 0-1 fraction of the track duration.
+                    let duration = state.track().duration;
+                    state.position = match target {
+                        t if t <= 1.0 => (t * duration as f64) as isize,
+                        t => (t as isize).min(duration),
+                    };
+
+                    (CommandResponse::ok(), vec![position_message(state)])
+                }
+                _ => (CommandResponse::error(400, "Invalid seek target"), vec![]),
+            }
+        }
+        ["transport", action] => {
+            match *action {
+                "play" => state.play_state = "play",
+                "pause" => state.play_state = "pause",
+                "stop" => {
+                    state.play_state = "stop";
+                    state.position = 0;
+                }
+                "toggle_playback" => {
+                    state.play_state = if state.play_state == "play" { "pause" } else { "play" };
+                }
+                "repeat" => state.repeat = !state.repeat,
+                "shuffle" => state.shuffle = !state.shuffle,
+                "next" | "previous" => {
+                    state.change_track(*action == "next");
+
+                    return (
+                        CommandResponse::ok(),
+                        vec![
+                            currently_playing_message(state),
+                            system_message(state),
+                            queue_message(state),
+                            position_message(state),
+                        ],
+                    );
+                }
+                _ => return (CommandResponse::error(404, "Unknown transport action"), vec![]),
+            }
+
+            (CommandResponse::ok(), vec![transport_message(state)])
+        }
+        ["queue", "play", "id", entry_id] => match state.queue_position_of(entry_id) {
+            Some(queue_position) => {
+                state.queue_position = queue_position;
+                state.position = 0;
+
+                (
+                    CommandResponse::ok(),
+                    vec![
+                        currently_playing_message(state),
+                        system_message(state),
+                        queue_message(state),
+                        position_message(state),
+                    ],
+                )
+            }
+            None => (CommandResponse::error(404, "Unknown queue entry"), vec![]),
+        },
+        ["queue", "delete", entry_id] => match state.queue_position_of(entry_id) {
+            Some(queue_position) if queue_position != state.queue_position => {
+                state.queue.remove(queue_position);
+
+                if queue_position < state.queue_position {
+                    state.queue_position -= 1;
+                }
+
+                (CommandResponse::ok(), vec![queue_message(state)])
+            }
+            Some(_) => (CommandResponse::error(400, "Cannot delete the playing entry"), vec![]),
+            None => (CommandResponse::error(404, "Unknown queue entry"), vec![]),
+        },
+        ["queue", "move", entry_id, from_position, to_position] => {
+            let from_position = from_position.parse::<usize>().ok();
+            let to_position = to_position.parse::<usize>().ok();
+
+            match (state.queue_position_of(entry_id), from_position, to_position) {
+                (Some(entry_position), Some(from), Some(to))
+                    if entry_position == from && to < state.queue.len() =>
+                {
+                    let playing_track = state.track_index();
+                    let track_index = state.queue.remove(from);
+                    state.queue.insert(to, track_index);
+                    state.queue_position =
+                        state.queue.iter().position(|index| *index == playing_track).unwrap();
+
+                    (CommandResponse::ok(), vec![queue_message(state)])
+                }
+                _ => (CommandResponse::error(400, "Invalid queue move"), vec![]),
+            }
+        }
+        _ => (CommandResponse::error(404, "Not found"), vec![]),
+    }
+}
+
+/// Decode a percent-encoded path segment, e.g. "Internet%20Radio". Invalid escapes are kept as-is.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = match bytes[index] {
+            b'%' => segment
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+async fn handle_http(
+    mut stream: TcpStream,
+    state_mutex: MockStateMutex,
+    updates: broadcast::Sender<String>,
+) -> std::io::Result<()> {
+    // The mock's commands don't have bodies, so reading the request head is enough.
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+
+        if read == 0 {
+            return Ok(());
+        }
+
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (response, messages) = {
+        let mut state = state_mutex.lock().unwrap();
+        apply_command(&mut state, method, path, &parse_query(query))
+    };
+
+    println!("{method} {target} -> {}", response.status);
+
+    for message in messages {
+        let _ = updates.send(message);
+    }
+
+    let body = response.body.to_string();
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                response.status,
+                body.len(),
+            )
+            .as_bytes(),
+        )
+        .await
+}
+
+// ------------------------------------------------------------------------------------------------
+// WebSocket handling
+
+async fn handle_websocket(
+    stream: TcpStream,
+    peer: SocketAddr,
+    config: Arc<MockConfig>,
+    state_mutex: MockStateMutex,
+    mut updates: broadcast::Receiver<String>,
+) {
+    let ws_stream = match accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            eprintln!("WebSocket handshake with {peer} failed: {:?}", e);
+            return;
+        }
+    };
+
+    println!("WebSocket client connected: {peer}");
+
+    let (mut write, mut read) = ws_stream.split();
+
+    let initial_messages = full_state_messages(&state_mutex.lock().unwrap());
+
+    for message in initial_messages {
+        if write.send(Message::Text(message)).await.is_err() {
+            return;
+        }
+    }
+
+    let connected_at = Instant::now();
+    let far_future = connected_at + Duration::from_secs(60 * 60 * 24 * 365);
+    let deadline = |after: Option<Duration>| after.map(|after| connected_at + after).unwrap_or(far_future);
+
+    let close_at = deadline(config.close_after);
+    let drop_at = deadline(config.drop_after);
+    let stall_at = deadline(config.stall_after);
+
+    let mut pinger = interval(config.ping_interval);
+    let mut is_stalled = false;
+
+    loop {
+        tokio::select! {
+            _ = pinger.tick(), if !is_stalled => {
+                if write.send(Message::Ping(vec![])).await.is_err() {
+                    break;
+                }
+            },
+            update = updates.recv(), if !is_stalled => {
+                match update {
+                    Ok(message) => {
+                        if write.send(Message::Text(message)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            },
+            incoming = read.next() => {
+                match incoming {
+                    Some(Ok(Message::Text(text))) => println!("WebSocket message from {peer}: {text}"),
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => {},
+                }
+            },
+            _ = sleep_until(close_at) => {
+                println!("Closing WebSocket connection to {peer}");
+                let _ = write.send(Message::Close(None)).await;
+                break;
+            },
+            _ = sleep_until(drop_at) => {
+                println!("Dropping WebSocket connection to {peer}");
+                return;
+            },
+            _ = sleep_until(stall_at), if !is_stalled => {
+                println!("Stalling WebSocket connection to {peer}");
+                is_stalled = true;
+            },
+        }
+    }
+
+    println!("WebSocket client disconnected: {peer}");
+}
+
+// ------------------------------------------------------------------------------------------------
+
+/// Peek at the request line to route the connection without consuming any of the request, which
+/// leaves the stream intact for the WebSocket handshake.
+async fn is_websocket_request(stream: &TcpStream) -> std::io::Result<bool> {
+    let mut buffer = [0u8; 256];
+
+    loop {
+        let peeked = stream.peek(&mut buffer).await?;
+        let request = &buffer[..peeked];
+
+        if peeked == 0 {
+            return Ok(false);
+        }
+
+        if request.windows(2).any(|window| window == b"\r\n") || peeked == buffer.len() {
+            let request_line = String::from_utf8_lossy(request);
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+
+            return Ok(path == "/ws" || path.starts_with("/ws?"));
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match MockConfig::from_args() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let listener = TcpListener::bind((config.host.as_str(), config.port))
+        .await
+        .expect("Could not bind mock Vibin listener");
+
+    println!("Mock Vibin listening on ws://{}:{}/ws", config.host, config.port);
+
+    let state_mutex: MockStateMutex = Arc::new(Mutex::new(MockState::new()));
+    let (updates, _) = broadcast::channel::<String>(64);
+
+    tokio::spawn(run_playback(state_mutex.clone(), updates.clone()));
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Could not accept connection: {:?}", e);
+                continue;
+            }
+        };
+
+        let config = config.clone();
+        let state_mutex = state_mutex.clone();
+        let updates = updates.clone();
+
+        tokio::spawn(async move {
+            match is_websocket_request(&stream).await {
+                Ok(true) => {
+                    let receiver = updates.subscribe();
+                    handle_websocket(stream, peer, config, state_mutex, receiver).await;
+                }
+                Ok(false) => {
+                    if let Err(e) = handle_http(stream, state_mutex, updates).await {
+                        eprintln!("HTTP request from {peer} failed: {:?}", e);
+                    }
+                }
+                Err(e) => eprintln!("Could not read request from {peer}: {:?}", e),
+            }
+        });
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+    use weevibin::vibin_api::VibinApiClient;
+
+    use crate::{handle_http, percent_decode, MockState, MockStateMutex};
+
+    #[test]
+    fn it_percent_decodes_path_segments() {
+        assert_eq!(percent_decode("Internet%20Radio"), "Internet Radio");
+        assert_eq!(percent_decode("AC%2FDC%3F"), "AC/DC?");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[tokio::test]
+    async fn it_switches_to_multi_word_sources() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state_mutex: MockStateMutex = Arc::new(Mutex::new(MockState::new()));
+        let (updates, _) = broadcast::channel::<String>(64);
+
+        let server_state = state_mutex.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let _ = handle_http(stream, server_state.clone(), updates.clone()).await;
+            }
+        });
+
+        let mut client = VibinApiClient::new();
+        client.set_vibin_host(&format!("ws://127.0.0.1:{port}/ws")).unwrap();
+
+        client.set_source("Internet Radio").await.unwrap();
+        assert_eq!(state_mutex.lock().unwrap().source().name, "Internet Radio");
+
+        client.set_source("Media Library").await.unwrap();
+        assert_eq!(state_mutex.lock().unwrap().source().name, "Media Library");
+    }
+}
@@ -0,0 +1,462 @@
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::{info, warn};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::async_runtime::{JoinHandle, Mutex as TauriMutex};
+use tokio::sync::oneshot;
+
+use crate::sink::StateSink;
+use crate::state::{ActiveTrack, AppStateMutex, Position, VibinStateMutex};
+use crate::vibin_api::{PowerAction, TransportAction, VibinApiClientMutex, VibinApiError};
+
+// ===============================================================================================
+// An optional HTTP/JSON API for controlling Vibin through weevibin, for shell scripts, Stream Deck
+// plugins, status bars, etc. It's served on localhost only, and uses weevibin's connection (and
+// the selected room in multi-room mode):
+//
+// GET /state - {"app_state": AppState, "vibin_state": VibinState}
+// GET /position - {"position": <seconds>}, or {"position": null} before any position is known.
+// POST /transport/<action> - e.g. play, pause, toggle_playback, next, previous (see
+//   TransportAction).
+// POST /volume/up, /volume/down, /volume/mute (toggles), /volume/<level> (0 to 1).
+// POST /power/on, /power/off, /power/toggle
+//
+// Every request needs an "Authorization: Bearer <token>" header, with the token generated for
+// this install (see ControlApiSettings). Being on localhost isn't enough: any web page open in a
+// browser can send simple POSTs to localhost, and a DNS-rebinding page can read the responses. So
+// requests with an Origin header (sent by browsers), or a Host other than 127.0.0.1:<port> or
+// localhost:<port>, are rejected too.
+//
+// POSTs respond with 204 (No Content) on success. Errors are {"error": "..."}, with a 502, 503, or
+// 504 status when Vibin couldn't be reached (see vibin_api_error_status). The settings are
+// persisted in the application's store (see main.rs) under CONTROL_API_SETTINGS_KEY.
+// ===============================================================================================
+
+pub const CONTROL_API_SETTINGS_KEY: &str = "control-api-settings";
+
+const DEFAULT_PORT: u16 = 7681;
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// The bearer token required by every request. Generated once, then persisted with the
+    /// settings (settings saved before tokens existed get one when they're loaded).
+    #[serde(default = "generate_token")]
+    pub token: String,
+}
+
+impl Default for ControlApiSettings {
+    fn default() -> Self {
+        ControlApiSettings { enabled: false, port: DEFAULT_PORT, token: generate_token() }
+    }
+}
+
+fn generate_token() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(TOKEN_LENGTH).map(char::from).collect()
+}
+
+pub type ControlApiSettingsMutex = Arc<Mutex<ControlApiSettings>>;
+
+// -----------------------------------------------------------------------------------------------
+
+struct RunningServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+/// Serves the control API, from the same state as the UI. Positions aren't part of the shared
+/// state, so the latest one is kept from what's emitted to its StateSink.
+pub struct ControlApi {
+    app_state: AppStateMutex,
+    vibin_state: VibinStateMutex,
+    vibin_api: VibinApiClientMutex,
+    position: Mutex<Option<Position>>,
+    server: TauriMutex<Option<RunningServer>>,
+}
+
+impl ControlApi {
+    pub fn new(
+        app_state: AppStateMutex,
+        vibin_state: VibinStateMutex,
+        vibin_api: VibinApiClientMutex,
+    ) -> Self {
+        ControlApi {
+            app_state,
+            vibin_state,
+            vibin_api,
+            position: Mutex::new(None),
+            server: TauriMutex::new(None),
+        }
+    }
+
+    /// Start, stop, or restart (e.g. on a different port) the server to match `settings`. Returns
+    /// the address being served, if enabled. Fails if the port can't be listened on (e.g. it's in
+    /// use), in which case the server is left stopped.
+    pub async fn apply(
+        self: &Arc<Self>,
+        settings: &ControlApiSettings,
+    ) -> Result<Option<SocketAddr>, String> {
+        let mut server = self.server.lock().await;
+
+        if let Some(running) = server.take() {
+            let _ = running.shutdown.send(());
+            let _ = running.handle.await;
+            info!("Stopped control API on {}", running.address);
+        }
+
+        if !settings.enabled {
+            return Ok(None);
+        }
+
+        let bind_address = SocketAddr::from((Ipv4Addr::LOCALHOST, settings.port));
+        let incoming = AddrIncoming::bind(&bind_address)
+            .map_err(|e| format!("Could not listen on port {}: {e}", settings.port))?;
+        let address = incoming.local_addr();
+
+        let access = Arc::new(Access::new(&settings.token, address.port()));
+        let control_api = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let control_api = control_api.clone();
+            let access = access.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let control_api = control_api.clone();
+                    let access = access.clone();
+
+                    async move { Ok::<_, Infallible>(control_api.handle(&access, request).await) }
+                }))
+            }
+        });
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let http_server = Server::builder(incoming).serve(make_service);
+
+        let handle = tauri::async_runtime::spawn(async move {
+            let result = http_server
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+
+            if let Err(e) = result {
+                warn!("Control API server error: {:?}", e);
+            }
+        });
+
+        info!("Serving control API on http://{}", address);
+        *server = Some(RunningServer { address, shutdown, handle });
+
+        Ok(Some(address))
+    }
+
+    async fn handle(&self, access: &Access, request: Request<Body>) -> Response<Body> {
+        if let Some(response) = access.reject(&request) {
+            return response;
+        }
+
+        let segments: Vec<&str> =
+            request.uri().path().split('/').filter(|segment| !segment.is_empty()).collect();
+
+        match (request.method(), &segments[..]) {
+            (&Method::GET, ["state"]) => {
+                let app_state = self.app_state.lock().unwrap().clone();
+                let vibin_state = self.vibin_state.lock().unwrap().clone();
+
+                json_response(
+                    StatusCode::OK,
+                    json!({ "app_state": app_state, "vibin_state": vibin_state }),
+                )
+            }
+            (&Method::GET, ["position"]) => {
+                let position = self.position.lock().unwrap().as_ref().map(|p| p.position);
+
+                json_response(StatusCode::OK, json!({ "position": position }))
+            }
+            (&Method::POST, ["transport", action]) => match parse_action(action) {
+                Some(action) => self.perform(Action::Transport(action)).await,
+                None => error_response(StatusCode::NOT_FOUND, "Unknown transport action"),
+            },
+            (&Method::POST, ["volume", "up"]) => self.perform(Action::VolumeUp).await,
+            (&Method::POST, ["volume", "down"]) => self.perform(Action::VolumeDown).await,
+            (&Method::POST, ["volume", "mute"]) => self.perform(Action::ToggleMute).await,
+            (&Method::POST, ["volume", level]) => match level.parse::<f32>() {
+                Ok(level) if (0.0..=1.0).contains(&level) => {
+                    self.perform(Action::SetVolume(level)).await
+                }
+                _ => error_response(StatusCode::BAD_REQUEST, "Volume must be between 0 and 1"),
+            },
+            (&Method::POST, ["power", action]) => match parse_action(action) {
+                Some(action) => self.perform(Action::Power(action)).await,
+                None => error_response(StatusCode::NOT_FOUND, "Unknown power action"),
+            },
+            (_, ["state" | "position"]) | (_, ["transport" | "volume" | "power", _]) => {
+                error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+            }
+            _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+
+    async fn perform(&self, action: Action) -> Response<Body> {
+        let client = self.vibin_api.lock().unwrap().clone();
+
+        let result = match action {
+            Action::Transport(action) => client.transport(action).await,
+            Action::VolumeUp => client.volume_up().await,
+            Action::VolumeDown => client.volume_down().await,
+            Action::ToggleMute => client.toggle_mute().await,
+            Action::SetVolume(level) => client.set_volume(level).await,
+            Action::Power(action) => client.power(action).await,
+        };
+
+        match result {
+            Ok(()) => {
+                Response::builder().status(StatusCode::NO_CONTENT).body(Body::empty()).unwrap()
+            }
+            Err(e) => error_response(vibin_api_error_status(&e), &e.to_string()),
+        }
+    }
+}
+
+impl StateSink for ControlApi {
+    fn emit_position(&self, position: &Position) {
+        *self.position.lock().unwrap() = Some(position.clone());
+    }
+
+    /// The previous track's position doesn't apply to the new one.
+    fn emit_track_change(&self, _active_track: &ActiveTrack) {
+        *self.position.lock().unwrap() = None;
+    }
+}
+
+/// What a request needs in order to be served (see the comment at the top).
+struct Access {
+    authorization: String,
+    hosts: [String; 2],
+}
+
+impl Access {
+    fn new(token: &str, port: u16) -> Self {
+        Access {
+            authorization: format!("Bearer {token}"),
+            hosts: [format!("127.0.0.1:{port}"), format!("localhost:{port}")],
+        }
+    }
+
+    /// The response rejecting the request, if it isn't allowed.
+    fn reject(&self, request: &Request<Body>) -> Option<Response<Body>> {
+        let headers = request.headers();
+
+        if headers.contains_key(header::ORIGIN) {
+            return Some(error_response(StatusCode::FORBIDDEN, "Browser requests aren't allowed"));
+        }
+
+        let host = headers.get(header::HOST).and_then(|host| host.to_str().ok());
+
+        if !host.is_some_and(|host| self.hosts.iter().any(|allowed| allowed == host)) {
+            return Some(error_response(StatusCode::FORBIDDEN, "Unexpected Host"));
+        }
+
+        let expected = self.authorization.as_bytes();
+        let authorization = headers.get(header::AUTHORIZATION).map(|value| value.as_bytes());
+
+        if !authorization.is_some_and(|value| constant_time_eq(value, expected)) {
+            return Some(error_response(StatusCode::UNAUTHORIZED, "Missing or invalid token"));
+        }
+
+        None
+    }
+}
+
+/// Compare without returning early, so the time taken doesn't reveal how much of a guessed token
+/// was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+enum Action {
+    Transport(TransportAction),
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    SetVolume(f32),
+    Power(PowerAction),
+}
+
+/// Parse an action from its path segment, e.g. "toggle_playback" (the actions' serde names).
+fn parse_action<T: serde::de::DeserializeOwned>(segment: &str) -> Option<T> {
+    serde_json::from_value(json!(segment)).ok()
+}
+
+fn vibin_api_error_status(error: &VibinApiError) -> StatusCode {
+    match error {
+        VibinApiError::HostNotSet => StatusCode::SERVICE_UNAVAILABLE,
+        VibinApiError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        VibinApiError::InvalidHost(_) | VibinApiError::Http { .. } | VibinApiError::Request(_) => {
+            StatusCode::BAD_GATEWAY
+        }
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "error": message }))
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use reqwest::header::{AUTHORIZATION, HOST, ORIGIN};
+    use reqwest::{Client, RequestBuilder, StatusCode};
+
+    use crate::control_api::{ControlApi, ControlApiSettings};
+    use crate::sink::StateSink;
+    use crate::state::{AppState, Position, VibinConnectionState, VibinState};
+    use crate::vibin_api::VibinApiClient;
+
+    const TOKEN: &str = "test-token";
+
+    fn enabled() -> ControlApiSettings {
+        ControlApiSettings { enabled: true, port: 0, token: TOKEN.into() }
+    }
+
+    fn control_api() -> Arc<ControlApi> {
+        let mut app_state = AppState::new();
+        app_state.vibin_connection = VibinConnectionState::Connected("vibin.local".into());
+
+        Arc::new(ControlApi::new(
+            Arc::new(Mutex::new(app_state)),
+            Arc::new(Mutex::new(VibinState::new())),
+            Arc::new(Mutex::new(VibinApiClient::new())),
+        ))
+    }
+
+    async fn send(request: RequestBuilder) -> (StatusCode, String) {
+        let response = request.send().await.unwrap();
+        let status = response.status();
+
+        (status, response.text().await.unwrap())
+    }
+
+    async fn get(address: SocketAddr, path: &str) -> (StatusCode, serde_json::Value) {
+        let request = Client::new().get(format!("http://{address}{path}")).bearer_auth(TOKEN);
+        let (status, body) = send(request).await;
+
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    async fn post(address: SocketAddr, path: &str) -> (StatusCode, String) {
+        send(Client::new().post(format!("http://{address}{path}")).bearer_auth(TOKEN)).await
+    }
+
+    #[tokio::test]
+    async fn it_serves_state_and_position() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+
+        assert!(address.ip().is_loopback());
+
+        let (status, state) = get(address, "/state").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state["app_state"]["vibin_connection"]["state"], "Connected");
+        assert!(state["vibin_state"].is_object());
+
+        assert_eq!(get(address, "/position").await.1["position"], serde_json::Value::Null);
+        control_api.emit_position(&Position { position: 42 });
+        assert_eq!(get(address, "/position").await.1["position"], 42);
+    }
+
+    #[tokio::test]
+    async fn it_reports_invalid_requests_and_vibin_api_errors() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+
+        assert_eq!(post(address, "/transport/rewind").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(post(address, "/volume/1.5").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(post(address, "/state").await.0, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(get(address, "/transport/play").await.0, StatusCode::METHOD_NOT_ALLOWED);
+
+        // The Vibin host hasn't been set.
+        let (status, body) = post(address, "/transport/toggle_playback").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains("Vibin host has not been set"));
+    }
+
+    #[tokio::test]
+    async fn it_stops_when_disabled() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+
+        let disabled = ControlApiSettings { enabled: false, port: address.port(), ..enabled() };
+        assert_eq!(control_api.apply(&disabled).await.unwrap(), None);
+
+        assert!(Client::new().get(format!("http://{address}/state")).send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_requires_the_token() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+        let url = format!("http://{address}/transport/pause");
+
+        assert_eq!(send(Client::new().post(&url)).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            send(Client::new().post(&url).bearer_auth("wrong-token")).await.0,
+            StatusCode::UNAUTHORIZED,
+        );
+        assert_eq!(
+            send(Client::new().post(&url).header(AUTHORIZATION, TOKEN)).await.0,
+            StatusCode::UNAUTHORIZED,
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_foreign_hosts() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+        let url = format!("http://{address}/state");
+
+        // e.g. a DNS-rebinding page, whose host name now resolves to 127.0.0.1.
+        let rebound = Client::new().get(&url).bearer_auth(TOKEN).header(HOST, "attacker.example");
+        assert_eq!(send(rebound).await.0, StatusCode::FORBIDDEN);
+
+        let localhost = format!("localhost:{}", address.port());
+        let local = Client::new().get(&url).bearer_auth(TOKEN).header(HOST, localhost);
+        assert_eq!(send(local).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_rejects_browser_requests() {
+        let control_api = control_api();
+        let address = control_api.apply(&enabled()).await.unwrap().unwrap();
+
+        let request = Client::new()
+            .post(format!("http://{address}/power/off"))
+            .bearer_auth(TOKEN)
+            .header(ORIGIN, "https://attacker.example");
+
+        assert_eq!(send(request).await.0, StatusCode::FORBIDDEN);
+    }
+}
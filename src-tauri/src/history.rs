@@ -0,0 +1,441 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+
+use crate::playback::{Play, PlaybackEvent, PlaybackTracker};
+use crate::sink::StateSink;
+use crate::state::{ActiveTrack, AppState, Position, VibinConnectionState, VibinState};
+
+// ===============================================================================================
+// The listening history: every play (see playback.rs) is recorded in a SQLite database in the
+// application's data directory once it ends, along with how long it was listened to and whether
+// it was skipped. Plays which were never listened to at all (e.g. a track shown while stopped)
+// aren't recorded.
+//
+// ListeningHistory is the database; HistoryRecorder is a StateSink which feeds it from the Vibin
+// WebSocket connection. The database schema is upgraded with MIGRATIONS, tracked by SQLite's
+// user_version.
+// ===============================================================================================
+
+pub const HISTORY_DATABASE: &str = "history.db";
+
+const MAX_PAGE_SIZE: usize = 500;
+
+// Each migration upgrades the schema by one version. Only ever append to this.
+const MIGRATIONS: [&str; 2] = [
+    "CREATE TABLE IF NOT EXISTS plays (
+        id INTEGER PRIMARY KEY,
+        title TEXT,
+        artist TEXT,
+        album TEXT,
+        album_media_id TEXT,
+        track_media_id TEXT,
+        source_class TEXT,
+        audio_format TEXT,
+        started_at INTEGER NOT NULL,
+        duration INTEGER,
+        listened_secs INTEGER NOT NULL,
+        skipped INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS plays_started_at ON plays (started_at);",
+    // The audio format details, for the stats' audio quality breakdown (see stats.rs).
+    "ALTER TABLE plays ADD COLUMN sample_rate INTEGER;
+    ALTER TABLE plays ADD COLUMN bit_depth INTEGER;
+    ALTER TABLE plays ADD COLUMN lossless INTEGER;",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_media_id: Option<String>,
+    pub track_media_id: Option<String>,
+    pub source_class: Option<String>,
+    /// The audio format summary, e.g. "FLAC 24/96 lossless".
+    pub audio_format: Option<String>,
+    pub sample_rate: Option<isize>,
+    pub bit_depth: Option<isize>,
+    pub lossless: Option<bool>,
+    /// When the play started, in seconds since the epoch.
+    pub started_at: i64,
+    pub duration: Option<isize>,
+    pub listened_secs: isize,
+    pub skipped: bool,
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+        Ok(HistoryEntry {
+            id: row.get("id")?,
+            title: row.get("title")?,
+            artist: row.get("artist")?,
+            album: row.get("album")?,
+            album_media_id: row.get("album_media_id")?,
+            track_media_id: row.get("track_media_id")?,
+            source_class: row.get("source_class")?,
+            audio_format: row.get("audio_format")?,
+            sample_rate: row.get("sample_rate")?,
+            bit_depth: row.get("bit_depth")?,
+            lossless: row.get("lossless")?,
+            started_at: row.get("started_at")?,
+            duration: row.get("duration")?,
+            listened_secs: row.get("listened_secs")?,
+            skipped: row.get("skipped")?,
+        })
+    }
+}
+
+/// One page of the history, most recent first. `total` is the number of entries across all pages.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub total: usize,
+}
+
+pub struct ListeningHistory {
+    connection: Mutex<Connection>,
+}
+
+impl ListeningHistory {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(directory) = path.parent() {
+            let _ = std::fs::create_dir_all(directory);
+        }
+
+        ListeningHistory::with_connection(Connection::open(path)?)
+    }
+
+    /// A history which isn't persisted (e.g. when the database can't be opened).
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        ListeningHistory::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut connection: Connection) -> rusqlite::Result<Self> {
+        ListeningHistory::migrate(&mut connection)?;
+
+        Ok(ListeningHistory { connection: Mutex::new(connection) })
+    }
+
+    fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index + 1)?;
+            transaction.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a query against the database (see stats.rs).
+    pub(crate) fn query<T>(
+        &self,
+        query: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<T> {
+        query(&self.connection.lock().unwrap())
+    }
+
+    pub fn record(&self, play: &Play) -> rusqlite::Result<()> {
+        let track = &play.track;
+        let audio_format = play.audio_format.as_ref();
+
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO plays (
+                title, artist, album, album_media_id, track_media_id, source_class, audio_format,
+                sample_rate, bit_depth, lossless, started_at, duration, listened_secs, skipped
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                track.title,
+                track.artist,
+                track.album,
+                track.album_media_id,
+                track.track_media_id,
+                play.source_class.as_ref().map(|class| class.to_string()),
+                audio_format.and_then(|format| format.summary.clone()),
+                audio_format.and_then(|format| format.sample_rate),
+                audio_format.and_then(|format| format.bit_depth),
+                audio_format.and_then(|format| format.lossless),
+                play.started_at,
+                track.duration,
+                play.played_secs,
+                !play.is_listened(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// A page of the history, most recent first. With a `search`, only entries whose title,
+    /// artist, or album contain it (ignoring case) are included.
+    pub fn page(
+        &self,
+        offset: usize,
+        limit: usize,
+        search: Option<&str>,
+    ) -> rusqlite::Result<HistoryPage> {
+        let pattern = match search.map(str::trim).filter(|search| !search.is_empty()) {
+            Some(search) => format!("%{}%", escape_like(search)),
+            None => String::from("%"),
+        };
+
+        // Entries without a title (etc) are still listed when there's no search.
+        let filter = "(?1 = '%'
+            OR title LIKE ?1 ESCAPE '\\'
+            OR artist LIKE ?1 ESCAPE '\\'
+            OR album LIKE ?1 ESCAPE '\\')";
+
+        let connection = self.connection.lock().unwrap();
+
+        let total: usize = connection.query_row(
+            &format!("SELECT COUNT(*) FROM plays WHERE {filter}"),
+            params![pattern],
+            |row| row.get(0),
+        )?;
+
+        let entries = connection
+            .prepare(&format!(
+                "SELECT * FROM plays WHERE {filter}
+                ORDER BY started_at DESC, id DESC
+                LIMIT ?2 OFFSET ?3"
+            ))?
+            .query_map(
+                params![pattern, limit.min(MAX_PAGE_SIZE), offset],
+                HistoryEntry::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<HistoryEntry>>>()?;
+
+        Ok(HistoryPage { entries, total })
+    }
+}
+
+/// Escape LIKE's wildcards, so they're matched literally.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+pub type ListeningHistoryRef = Arc<ListeningHistory>;
+
+// -----------------------------------------------------------------------------------------------
+
+/// Records each play in the listening history once it ends.
+pub struct HistoryRecorder {
+    history: ListeningHistoryRef,
+    tracker: Mutex<PlaybackTracker>,
+}
+
+impl HistoryRecorder {
+    pub fn new(history: ListeningHistoryRef) -> Self {
+        HistoryRecorder { history, tracker: Mutex::new(PlaybackTracker::new()) }
+    }
+
+    /// Record the play in progress, if any (e.g. when weevibin is quitting).
+    pub fn flush(&self) {
+        let event = self.tracker.lock().unwrap().end();
+        self.record(event.into_iter().collect());
+    }
+
+    fn record(&self, events: Vec<PlaybackEvent>) {
+        for event in events {
+            if let PlaybackEvent::Ended(play) = event {
+                if play.played_secs == 0 {
+                    continue;
+                }
+
+                if let Err(e) = self.history.record(&play) {
+                    let title = play.track.title.unwrap_or_default();
+                    warn!("Could not record {:?} in the listening history: {:?}", title, e);
+                }
+            }
+        }
+    }
+}
+
+impl StateSink for HistoryRecorder {
+    /// A play can't be followed without a connection, so it ends when the connection is lost.
+    fn emit_app_state(&self, app_state: &AppState) {
+        if !matches!(app_state.vibin_connection, VibinConnectionState::Connected(_)) {
+            self.flush();
+        }
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        let events = self.tracker.lock().unwrap().vibin_state_changed(vibin_state);
+        self.record(events);
+    }
+
+    fn emit_position(&self, position: &Position) {
+        let events = self.tracker.lock().unwrap().position_changed(position.position);
+        self.record(events);
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        let events = self.tracker.lock().unwrap().track_changed(active_track);
+        self.record(events);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rusqlite::Connection;
+
+    use crate::history::{HistoryRecorder, ListeningHistory, MIGRATIONS};
+    use crate::playback::Play;
+    use crate::sink::StateSink;
+    use crate::state::{
+        ActiveTrack, AppState, AudioFormat, PlayState, Position, SourceClass, TransportState,
+        VibinConnectionState, VibinState,
+    };
+
+    fn play(title: &str, artist: &str, started_at: i64, played_secs: isize) -> Play {
+        Play {
+            track: ActiveTrack {
+                title: Some(title.into()),
+                artist: Some(artist.into()),
+                album: Some("Home".into()),
+                art_url: None,
+                duration: Some(200),
+                album_media_id: Some("album-1".into()),
+                track_media_id: Some(format!("track-{started_at}")),
+                stream_url: None,
+            },
+            source_class: Some(SourceClass::StreamMedia),
+            audio_format: Some(
+                AudioFormat {
+                    sample_format: None,
+                    mqa: None,
+                    codec: Some("flac".into()),
+                    lossless: Some(true),
+                    sample_rate: Some(96000),
+                    bit_depth: Some(24),
+                    encoding: None,
+                    summary: None,
+                }
+                .with_summary(),
+            ),
+            started_at,
+            played_secs,
+        }
+    }
+
+    #[test]
+    fn it_records_plays() {
+        let history = ListeningHistory::open_in_memory().unwrap();
+
+        history.record(&play("Leaving", "Hania Rani", 100, 150)).unwrap();
+        history.record(&play("Glass", "Hania Rani", 200, 20)).unwrap();
+
+        let page = history.page(0, 10, None).unwrap();
+        assert_eq!(page.total, 2);
+
+        let [latest, earliest] = &page.entries[..] else {
+            panic!("Expected two entries, got {:?}", page.entries);
+        };
+
+        assert_eq!(latest.title.as_deref(), Some("Glass"));
+        assert!(latest.skipped);
+        assert_eq!(earliest.track_media_id.as_deref(), Some("track-100"));
+        assert_eq!(earliest.source_class.as_deref(), Some("stream.media"));
+        assert!(earliest.audio_format.as_deref().is_some_and(|format| format.contains("FLAC")));
+        assert_eq!(earliest.sample_rate, Some(96000));
+        assert_eq!(earliest.lossless, Some(true));
+        assert_eq!(earliest.listened_secs, 150);
+        assert!(!earliest.skipped);
+    }
+
+    #[test]
+    fn it_records_the_play_in_progress_when_the_connection_is_lost() {
+        let history = Arc::new(ListeningHistory::open_in_memory().unwrap());
+        let recorder = HistoryRecorder::new(history.clone());
+
+        let mut vibin_state = VibinState::new();
+        vibin_state.transport = Some(TransportState {
+            play_state: Some(PlayState::Play),
+            active_controls: Vec::new(),
+            repeat: None,
+            shuffle: None,
+        });
+
+        let mut app_state = AppState::new();
+        app_state.vibin_connection = VibinConnectionState::Connected("Connected".into());
+
+        recorder.emit_app_state(&app_state);
+        recorder.emit_vibin_state(&vibin_state);
+        recorder.emit_track_change(&play("Leaving", "Hania Rani", 0, 0).track);
+
+        for position in 0..=30 {
+            recorder.emit_position(&Position { position });
+        }
+
+        assert_eq!(history.page(0, 10, None).unwrap().total, 0);
+
+        app_state.vibin_connection = VibinConnectionState::Disconnected(None);
+        recorder.emit_app_state(&app_state);
+
+        let page = history.page(0, 10, None).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].listened_secs, 30);
+
+        // The play is only recorded once.
+        recorder.flush();
+        assert_eq!(history.page(0, 10, None).unwrap().total, 1);
+    }
+
+    #[test]
+    fn it_pages_and_searches() {
+        let history = ListeningHistory::open_in_memory().unwrap();
+
+        for index in 0..5 {
+            history.record(&play(&format!("Track {index}"), "Nils Frahm", index, 10)).unwrap();
+        }
+        history.record(&play("100%_Pure", "Joep Beving", 10, 10)).unwrap();
+
+        let page = history.page(1, 2, None).unwrap();
+        assert_eq!(page.total, 6);
+        assert_eq!(
+            page.entries.iter().map(|entry| entry.title.clone().unwrap()).collect::<Vec<_>>(),
+            vec!["Track 4", "Track 3"]
+        );
+
+        assert_eq!(history.page(0, 10, Some("nils")).unwrap().total, 5);
+        assert_eq!(history.page(0, 10, Some("track 2")).unwrap().total, 1);
+
+        // Wildcards in the search are matched literally.
+        assert_eq!(history.page(0, 10, Some("%_")).unwrap().total, 1);
+        assert_eq!(history.page(0, 10, Some("_")).unwrap().total, 1);
+    }
+
+    #[test]
+    fn it_upgrades_older_databases() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection
+            .execute(
+                "INSERT INTO plays (title, started_at, listened_secs, skipped)
+                VALUES ('Says', 100, 300, 0)",
+                [],
+            )
+            .unwrap();
+
+        let history = ListeningHistory::with_connection(connection).unwrap();
+        history.record(&play("Hammers", "Nils Frahm", 200, 300)).unwrap();
+
+        let page = history.page(0, 10, None).unwrap();
+
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries[1].title.as_deref(), Some("Says"));
+        assert_eq!(page.entries[1].bit_depth, None);
+        assert_eq!(page.entries[0].bit_depth, Some(24));
+    }
+}
@@ -1,3 +1,17 @@
 pub mod average;
+pub mod backoff;
+pub mod control_api;
+pub mod history;
+pub mod notifications;
+pub mod playback;
+pub mod profiles;
+pub mod recording;
+pub mod rooms;
+pub mod scrobble;
+pub mod shortcuts;
+pub mod sink;
 pub mod state;
+pub mod stats;
+pub mod tray;
+pub mod vibin_api;
 pub mod websocket;
@@ -2,18 +2,55 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use log::{info};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tauri::async_runtime::Mutex as TauriMutex;
-use tauri::{CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu};
+use tauri::{GlobalShortcutManager, Manager, SystemTray, SystemTrayEvent, Wry};
 use tauri_plugin_log::{LogTarget, TimezoneStrategy};
 use tauri_plugin_log::fern::colors::ColoredLevelConfig;
 use tauri_plugin_positioner::{Position, WindowExt};
+use tauri_plugin_store::{with_store, StoreCollection};
 use time;
 // use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial, NSVisualEffectState};
 
-use weevibin::state::{AppState, AppStateMutex, WeeVibinMessage, VibinState, VibinStateMutex};
-use weevibin::websocket::{WebSocketManager, WebSocketManagerMutex};
+use weevibin::backoff::{ReconnectPolicy, RECONNECT_POLICY_KEY};
+use weevibin::control_api::{
+    ControlApi, ControlApiSettings, ControlApiSettingsMutex, CONTROL_API_SETTINGS_KEY,
+};
+use weevibin::history::{
+    HistoryPage, HistoryRecorder, ListeningHistory, ListeningHistoryRef, HISTORY_DATABASE,
+};
+use weevibin::notifications::{
+    NotificationSettings, NotificationSettingsMutex, TrackNotifier, NOTIFICATION_SETTINGS_KEY,
+};
+use weevibin::profiles::{ServerProfile, ServerProfiles, ServerProfilesMutex, SERVER_PROFILES_KEY};
+use weevibin::recording::{resolve_recording, RECORDINGS_DIR};
+use weevibin::rooms::{RoomManager, RoomManagerMutex, RoomSummary, TauriRoomEventSink};
+use weevibin::scrobble::{
+    ScrobbleSettings, ScrobbleSettingsMutex, Scrobbler, SCROBBLE_SETTINGS_KEY,
+};
+use weevibin::shortcuts::{
+    ShortcutAction, ShortcutBindings, ShortcutBindingsMutex, SHORTCUT_BINDINGS_KEY,
+};
+use weevibin::sink::{CompositeStateSink, StateSink, StateSinkRef, TauriStateSink};
+use weevibin::state::{
+    AppError, AppErrorCategory, AppState, AppStateMutex, VibinState, VibinStateMutex,
+    WeeVibinMessage,
+};
+use weevibin::stats::{listening_stats, ListeningStats, StatsPeriod};
+use weevibin::tray::{
+    build_tray_menu, play_pause_action, TrayMenu, TrayMenuState, TrayStatusDisplay, MUTE_ID,
+    NEXT_ID, PLAY_PAUSE_ID, POWER_ID, PREVIOUS_ID, PROFILE_ID_PREFIX, QUIT_ID, SOURCE_ID_PREFIX,
+};
+use weevibin::vibin_api::{
+    vibinui_url, PowerAction, TransportAction, VibinApiClient, VibinApiClientMutex, VibinApiError,
+};
+use weevibin::websocket::{
+    VibinCommand, WebSocketConnection, WebSocketManager, WebSocketManagerMutex,
+};
 
 // TODO: Hide when clicking on menu bar away from app window <-- SEEMS OK NOW?
 // TODO: Have UI properly show on current virtual desktop rather than always activating the desktop
@@ -25,10 +62,14 @@ const LOG_TARGETS: [LogTarget; 3] = [LogTarget::Stdout, LogTarget::Webview, LogT
 #[cfg(not(debug_assertions))]
 const LOG_TARGETS: [LogTarget; 2] = [LogTarget::Stdout, LogTarget::LogDir];
 
+// The store shared with the UI (see persisted_state.ts).
+const STORE_PATH: &str = "weevibin.dat";
+
 /// Called by the UI once it's ready. There's probably a different idiomatic-Tauri way to do this.
 #[tauri::command]
 async fn on_ui_ready(
     ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    room_manager: tauri::State<'_, RoomManagerMutex>,
     app_state: tauri::State<'_, AppStateMutex>,
     vibin_state: tauri::State<'_, VibinStateMutex>,
     app_handle: tauri::AppHandle,
@@ -40,31 +81,39 @@ async fn on_ui_ready(
         .emit_all(&WeeVibinMessage::VibinState.to_string(), &*vibin_state.inner().lock().unwrap())
         .unwrap();
 
-    ws_manager.inner().lock().await.start();
+    // In multi-room mode the rooms have their own connections.
+    if !room_manager.inner().lock().await.is_active() {
+        ws_manager.inner().lock().await.start();
+    }
 
     Ok(())
 }
 
 /// Set the Vibin WebSocket server URL. e.g. ws://vibin.local:8080/ws
+///
+/// When `startup_retry` is true (used when reconnecting to the persisted host at startup), failed
+/// connection attempts are retried even if the host has never been successfully connected to.
 #[tauri::command]
 async fn set_vibin_server(
     vibin_server: String,
+    startup_retry: Option<bool>,
     ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    room_manager: tauri::State<'_, RoomManagerMutex>,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
     app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
     match url::Url::parse(vibin_server.as_str()) {
         Ok(_) => {
             info!("UI requested new Vibin server: {:?}", vibin_server);
 
-            info!("Waiting for WebSocket manager lock");
-            let mut manager = ws_manager.inner().lock().await;
-
-            info!("Waiting for WebSocket disconnect");
-            manager.stop().await;
-
-            info!("Setting server name: {:?}", vibin_server);
-            manager.vibin_host = Some(Box::new(vibin_server));
-            manager.start();
+            connect_to_vibin_server(
+                vibin_server,
+                startup_retry.unwrap_or(false),
+                ws_manager.inner(),
+                room_manager.inner(),
+                vibin_api.inner(),
+            )
+            .await;
 
             Ok(String::from("OK"))
         }
@@ -77,35 +126,982 @@ async fn set_vibin_server(
     }
 }
 
+/// Cleanly disconnect from the current Vibin server (if any) and connect to `vibin_server`. This
+/// leaves multi-room mode.
+async fn connect_to_vibin_server(
+    vibin_server: String,
+    startup_retry: bool,
+    ws_manager: &WebSocketManagerMutex,
+    room_manager: &RoomManagerMutex,
+    vibin_api: &VibinApiClientMutex,
+) {
+    if let Err(e) = vibin_api.lock().unwrap().set_vibin_host(&vibin_server) {
+        warn!("Could not set Vibin REST API host: {}", e);
+    }
+
+    {
+        let mut rooms = room_manager.lock().await;
+
+        if rooms.is_active() {
+            info!("Leaving multi-room mode");
+            rooms.stop_all().await;
+        }
+    }
+
+    info!("Waiting for WebSocket manager lock");
+    let mut manager = ws_manager.lock().await;
+
+    info!("Waiting for WebSocket disconnect");
+    manager.stop().await;
+
+    info!("Setting server name: {:?}", vibin_server);
+    manager.vibin_host = Some(Box::new(vibin_server));
+    manager.set_startup_retry(startup_retry);
+    manager.start();
+}
+
+// ------------------------------------------------------------------------------------------------
+// Settings persisted in the store.
+
+/// Load the value stored under `key`, or the default if there isn't one (or it can't be read).
+fn load_from_store<T: DeserializeOwned + Default>(app_handle: &tauri::AppHandle, key: &str) -> T {
+    let stores = app_handle.state::<StoreCollection<Wry>>();
+
+    let persisted = with_store(app_handle.clone(), stores, STORE_PATH, |store| {
+        Ok(store.get(key).cloned())
+    });
+
+    match persisted {
+        Ok(Some(value)) => serde_json::from_value(value).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable {}: {:?}", key, e);
+            T::default()
+        }),
+        Ok(None) => T::default(),
+        Err(e) => {
+            warn!("Could not load {}: {:?}", key, e);
+            T::default()
+        }
+    }
+}
+
+fn save_to_store<T: Serialize>(
+    app_handle: &tauri::AppHandle,
+    key: &str,
+    value: &T,
+) -> Result<(), String> {
+    let stores = app_handle.state::<StoreCollection<Wry>>();
+    let value =
+        serde_json::to_value(value).map_err(|e| format!("Could not serialize {key}: {:?}", e))?;
+
+    with_store(app_handle.clone(), stores, STORE_PATH, |store| {
+        store.insert(key.to_string(), value)?;
+        store.save()
+    })
+    .map_err(|e| format!("Could not save {key}: {:?}", e))
+}
+
+// ------------------------------------------------------------------------------------------------
+// Vibin server profiles. These are persisted in the store, and listed in the tray menu.
+
+/// Persist the profiles, and let the tray menu and UI know about the change.
+fn server_profiles_changed(
+    app_handle: &tauri::AppHandle,
+    profiles: &ServerProfiles,
+) -> Result<(), String> {
+    save_to_store(app_handle, SERVER_PROFILES_KEY, profiles)?;
+
+    app_handle.state::<Arc<TrayMenu>>().update_profiles(profiles);
+    app_handle.emit_all(&WeeVibinMessage::ServerProfiles.to_string(), profiles).unwrap();
+
+    Ok(())
+}
+
+/// Make the named profile the active one, and connect to its Vibin server.
+async fn switch_to_server_profile(
+    name: &str,
+    app_handle: &tauri::AppHandle,
+) -> Result<ServerProfile, String> {
+    let profiles_mutex = app_handle.state::<ServerProfilesMutex>();
+
+    let (profile, profiles) = {
+        let mut profiles = profiles_mutex.lock().unwrap();
+        let profile = profiles.activate(name)?;
+
+        (profile, profiles.clone())
+    };
+
+    info!("Switching to Vibin server profile {:?}: {}", profile.name, profile.host);
+    server_profiles_changed(app_handle, &profiles)?;
+
+    let room_manager = app_handle.state::<RoomManagerMutex>();
+    let vibin_api = app_handle.state::<VibinApiClientMutex>();
+
+    // In multi-room mode every profile is already connected, so switching profiles only changes
+    // which room is being controlled.
+    {
+        let rooms = room_manager.inner().lock().await;
+
+        if rooms.is_active() {
+            select_room(&rooms, &profile.name, vibin_api.inner())?;
+            return Ok(profile);
+        }
+    }
+
+    connect_to_vibin_server(
+        profile.host.clone(),
+        false,
+        app_handle.state::<WebSocketManagerMutex>().inner(),
+        room_manager.inner(),
+        vibin_api.inner(),
+    )
+    .await;
+
+    Ok(profile)
+}
+
+#[tauri::command]
+fn get_server_profiles(server_profiles: tauri::State<'_, ServerProfilesMutex>) -> ServerProfiles {
+    server_profiles.inner().lock().unwrap().clone()
+}
+
+/// Save a profile for the given Vibin WebSocket URL, replacing any profile with the same name.
+#[tauri::command]
+async fn save_server_profile(
+    name: String,
+    host: String,
+    server_profiles: tauri::State<'_, ServerProfilesMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    url::Url::parse(&host).map_err(|e| format!("Invalid URL: {:?}", e))?;
+
+    let profiles = {
+        let mut profiles = server_profiles.inner().lock().unwrap();
+        profiles.save(ServerProfile { name, host })?;
+        profiles.clone()
+    };
+
+    server_profiles_changed(&app_handle, &profiles)?;
+    sync_rooms(&app_handle, &profiles).await
+}
+
+#[tauri::command]
+async fn delete_server_profile(
+    name: String,
+    server_profiles: tauri::State<'_, ServerProfilesMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let profiles = {
+        let mut profiles = server_profiles.inner().lock().unwrap();
+        profiles.delete(&name)?;
+        profiles.clone()
+    };
+
+    server_profiles_changed(&app_handle, &profiles)?;
+    sync_rooms(&app_handle, &profiles).await
+}
+
+#[tauri::command]
+async fn switch_server_profile(
+    name: String,
+    app_handle: tauri::AppHandle,
+) -> Result<ServerProfile, String> {
+    switch_to_server_profile(&name, &app_handle).await
+}
+
+// ------------------------------------------------------------------------------------------------
+// Multi-room mode. Every server profile is a room with its own connection (see rooms.rs). The
+// selected room is the active profile, and is the room the controls and REST API commands target.
+
+/// Select the room the controls target, pointing the REST API client at its Vibin server.
+fn select_room(
+    rooms: &RoomManager,
+    room_id: &str,
+    vibin_api: &VibinApiClientMutex,
+) -> Result<(), String> {
+    let host = rooms.select_room(room_id)?;
+
+    vibin_api.lock().unwrap().set_vibin_host(&host).map_err(|e| e.to_string())
+}
+
+/// Bring the rooms in line with the profiles after a profile change (in multi-room mode).
+async fn sync_rooms(
+    app_handle: &tauri::AppHandle,
+    profiles: &ServerProfiles,
+) -> Result<(), String> {
+    let room_manager = app_handle.state::<RoomManagerMutex>();
+    let mut rooms = room_manager.inner().lock().await;
+
+    if !rooms.is_active() {
+        return Ok(());
+    }
+
+    rooms.sync_rooms(&profiles.profiles).await;
+    let is_active = rooms.is_active();
+    drop(rooms);
+
+    // The selected room may have been removed, or reconnected to a different host.
+    match profiles.active_profile().or(profiles.profiles.first()) {
+        Some(profile) if is_active => {
+            switch_to_server_profile(&profile.name, app_handle).await.map(|_| ())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Enable or disable multi-room mode. Enabling connects to every server profile, and selects the
+/// active profile's room (or the first room). Disabling keeps the selected room connected.
+#[tauri::command]
+async fn set_multi_room_mode(
+    enabled: bool,
+    server_profiles: tauri::State<'_, ServerProfilesMutex>,
+    ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    room_manager: tauri::State<'_, RoomManagerMutex>,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<RoomSummary>, String> {
+    let profiles = server_profiles.inner().lock().unwrap().clone();
+    let mut rooms = room_manager.inner().lock().await;
+
+    if enabled == rooms.is_active() {
+        return Ok(rooms.rooms());
+    }
+
+    if enabled {
+        let Some(first_profile) = profiles.profiles.first() else {
+            return Err(String::from("Multi-room mode requires at least one server profile"));
+        };
+        let selected_profile = profiles.active_profile().unwrap_or(first_profile).clone();
+
+        info!("Entering multi-room mode");
+        ws_manager.inner().lock().await.stop().await;
+        rooms.sync_rooms(&profiles.profiles).await;
+        drop(rooms);
+
+        switch_to_server_profile(&selected_profile.name, &app_handle).await?;
+    } else {
+        let selected_host = rooms
+            .rooms()
+            .into_iter()
+            .find(|room| room.selected)
+            .map(|room| room.host);
+        drop(rooms);
+
+        // connect_to_vibin_server() leaves multi-room mode.
+        if let Some(host) = selected_host {
+            connect_to_vibin_server(
+                host,
+                false,
+                ws_manager.inner(),
+                room_manager.inner(),
+                vibin_api.inner(),
+            )
+            .await;
+        } else {
+            room_manager.inner().lock().await.stop_all().await;
+        }
+    }
+
+    Ok(room_manager.inner().lock().await.rooms())
+}
+
+#[tauri::command]
+async fn get_rooms(
+    room_manager: tauri::State<'_, RoomManagerMutex>,
+) -> Result<Vec<RoomSummary>, String> {
+    Ok(room_manager.inner().lock().await.rooms())
+}
+
+/// Send a command to Vibin over the WebSocket connection used to receive Vibin state.
+#[tauri::command]
+async fn send_vibin_command(
+    command: String,
+    payload: Option<serde_json::Value>,
+    ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    room_manager: tauri::State<'_, RoomManagerMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let vibin_command = VibinCommand {
+        msg_type: command,
+        payload: payload.unwrap_or(serde_json::Value::Null),
+    };
+
+    let result = {
+        let rooms = room_manager.inner().lock().await;
+
+        match rooms.is_active() {
+            true => rooms.send_command(&vibin_command),
+            false => ws_manager.inner().lock().await.send_command(&vibin_command),
+        }
+    };
+
+    result.map_err(|error| {
+        app_handle
+            .emit_all(&WeeVibinMessage::Error.to_string(), AppError {
+                category: AppErrorCategory::WebSocket,
+                message: error.clone(),
+            })
+            .unwrap();
+
+        error
+    })
+}
+
+/// Enable or disable recording of Vibin WebSocket sessions. Recording starts with the next
+/// connection, and is written to the "recordings" directory in the application data directory.
+/// Returns the recordings directory when enabled.
+#[tauri::command]
+async fn set_session_recording(
+    enabled: bool,
+    ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let recording_dir = match enabled {
+        true => Some(
+            app_handle
+                .path_resolver()
+                .app_data_dir()
+                .ok_or("Could not determine application data directory")?
+                .join(RECORDINGS_DIR),
+        ),
+        false => None,
+    };
+
+    info!("Vibin WebSocket session recording directory: {:?}", recording_dir);
+    ws_manager.inner().lock().await.set_recording_dir(recording_dir.clone());
+
+    Ok(recording_dir.map(|dir| dir.to_string_lossy().to_string()))
+}
+
+/// Replay a recorded Vibin WebSocket session, as though it was being received from Vibin. A
+/// `speed` of 1 (the default) uses the original timing; 0 replays without delays. `recording` must
+/// be in the recordings directory (see set_session_recording).
+///
+/// The replay only updates the UI and the tray: it's not notified, scrobbled, or recorded in the
+/// listening history, and it doesn't touch the global VibinState. The Vibin connection is stopped
+/// for the duration of the replay, after which the live state is restored and the connection is
+/// restarted.
+#[tauri::command]
+async fn replay_vibin_session(
+    recording: String,
+    speed: Option<f64>,
+    ws_manager: tauri::State<'_, WebSocketManagerMutex>,
+    room_manager: tauri::State<'_, RoomManagerMutex>,
+    app_state: tauri::State<'_, AppStateMutex>,
+    vibin_state: tauri::State<'_, VibinStateMutex>,
+    tray_menu: tauri::State<'_, Arc<TrayMenu>>,
+    tray_status: tauri::State<'_, Arc<TrayStatusDisplay>>,
+    app_handle: tauri::AppHandle,
+) -> Result<usize, String> {
+    let recordings_dir = app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("Could not determine application data directory")?
+        .join(RECORDINGS_DIR);
+    let recording = resolve_recording(&recordings_dir, std::path::Path::new(&recording))?;
+
+    if room_manager.inner().lock().await.is_active() {
+        return Err(String::from("Leave multi-room mode before replaying a session"));
+    }
+
+    // The manager stays locked during the replay, so it can't be restarted until it's finished.
+    let mut manager = ws_manager.inner().lock().await;
+    let was_started = *manager.is_started.lock().unwrap();
+
+    if was_started {
+        manager.stop().await;
+    }
+
+    let sink: StateSinkRef = Arc::new(CompositeStateSink::new(vec![
+        Arc::new(TauriStateSink::new(app_handle)),
+        tray_menu.inner().clone(),
+        tray_status.inner().clone(),
+    ]));
+    let replay_state: VibinStateMutex = Arc::new(Mutex::new(VibinState::new()));
+
+    let result = WebSocketConnection::new()
+        .replay(&recording, speed.unwrap_or(1.0), &replay_state, sink.clone())
+        .await;
+
+    sink.emit_app_state(&app_state.inner().lock().unwrap());
+    sink.emit_vibin_state(&vibin_state.inner().lock().unwrap());
+
+    if was_started {
+        manager.start();
+    }
+
+    result
+}
+
+// ------------------------------------------------------------------------------------------------
+// Vibin REST API commands. Failures are emitted as errors to the UI (as well as being returned to
+// the caller).
+
+fn report_vibin_api_error(app_handle: &tauri::AppHandle, error: VibinApiError) -> String {
+    let message = error.to_string();
+    warn!("Vibin API error: {}", message);
+
+    app_handle
+        .emit_all(&WeeVibinMessage::Error.to_string(), AppError {
+            category: AppErrorCategory::VibinApi,
+            message: message.clone(),
+        })
+        .unwrap();
+
+    message
+}
+
+#[tauri::command]
+async fn vibin_power(
+    action: PowerAction,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.power(action).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_transport(
+    action: TransportAction,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.transport(action).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_seek(
+    target: f64,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.seek(target).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_toggle_mute(
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.toggle_mute().await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_volume_up(
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.volume_up().await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_volume_down(
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.volume_down().await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_volume_set(
+    level: f32,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.set_volume(level).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+/// Switch the streamer's input to the source with the given id (see VibinState.sources).
+async fn set_source(
+    source_id: &str,
+    vibin_api: &VibinApiClientMutex,
+    vibin_state: &VibinStateMutex,
+    app_handle: &tauri::AppHandle,
+) -> Result<(), String> {
+    // Vibin selects sources by name, but names can be changed on the streamer. Ids are stable, so
+    // callers identify sources by id.
+    let source_name = vibin_state
+        .lock()
+        .unwrap()
+        .source_by_id(source_id)
+        .and_then(|source| source.name.clone())
+        .unwrap_or(source_id.to_string());
+
+    let client = vibin_api.lock().unwrap().clone();
+    client.set_source(&source_name).await.map_err(|e| report_vibin_api_error(app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_set_source(
+    source_id: String,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    vibin_state: tauri::State<'_, VibinStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    set_source(&source_id, vibin_api.inner(), vibin_state.inner(), &app_handle).await
+}
+
+#[tauri::command]
+async fn vibin_queue_play(
+    entry_id: isize,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.queue_play(entry_id).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_queue_delete(
+    entry_id: isize,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client.queue_delete(entry_id).await.map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+#[tauri::command]
+async fn vibin_queue_move(
+    entry_id: isize,
+    from_position: usize,
+    to_position: usize,
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let client = vibin_api.inner().lock().unwrap().clone();
+    client
+        .queue_move(entry_id, from_position, to_position)
+        .await
+        .map_err(|e| report_vibin_api_error(&app_handle, e))
+}
+
+/// What "play/pause" means depends on what the streamer currently allows.
+fn current_play_pause_action(app_handle: &tauri::AppHandle) -> Option<TransportAction> {
+    let vibin_state = app_handle.state::<VibinStateMutex>();
+    let transport = vibin_state.lock().unwrap().transport.clone();
+
+    transport.as_ref().and_then(play_pause_action)
+}
+
+/// Perform the Vibin action for a tray menu item (play/pause, next, mute, etc). Failures are
+/// reported to the UI.
+async fn perform_tray_action(id: &str, app_handle: &tauri::AppHandle) {
+    let client = app_handle.state::<VibinApiClientMutex>().lock().unwrap().clone();
+
+    let result = match id {
+        PLAY_PAUSE_ID => match current_play_pause_action(app_handle) {
+            Some(action) => client.transport(action).await,
+            None => return,
+        },
+        NEXT_ID => client.transport(TransportAction::Next).await,
+        PREVIOUS_ID => client.transport(TransportAction::Previous).await,
+        MUTE_ID => client.toggle_mute().await,
+        POWER_ID => client.power(PowerAction::Toggle).await,
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        report_vibin_api_error(app_handle, e);
+    }
+}
+
+/// Open the full Vibin Web UI (vibinui) in the default browser, showing the current track.
+#[tauri::command]
+async fn open_in_vibinui(
+    vibin_api: tauri::State<'_, VibinApiClientMutex>,
+    vibin_state: tauri::State<'_, VibinStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let base_url = vibin_api.inner().lock().unwrap().base_url().cloned();
+    let Some(base_url) = base_url else {
+        return Err(report_vibin_api_error(&app_handle, VibinApiError::HostNotSet));
+    };
+
+    let url = {
+        let vibin_state = vibin_state.inner().lock().unwrap();
+        vibinui_url(&base_url, vibin_state.active_track.as_ref())
+    };
+
+    info!("Opening vibinui: {url}");
+
+    tauri::api::shell::open(&app_handle.shell_scope(), url.as_str(), None)
+        .map_err(|e| format!("Could not open vibinui: {:?}", e))
+}
+
+// ------------------------------------------------------------------------------------------------
+// Global shortcuts. These perform Vibin actions without the UI being shown. Bindings are persisted
+// in the store.
+
+async fn perform_shortcut_action(action: ShortcutAction, app_handle: &tauri::AppHandle) {
+    let client = app_handle.state::<VibinApiClientMutex>().lock().unwrap().clone();
+
+    let result = match action {
+        ShortcutAction::PlayPause => match current_play_pause_action(app_handle) {
+            Some(transport_action) => client.transport(transport_action).await,
+            None => return,
+        },
+        ShortcutAction::Next => client.transport(TransportAction::Next).await,
+        ShortcutAction::Previous => client.transport(TransportAction::Previous).await,
+        ShortcutAction::VolumeUp => client.volume_up().await,
+        ShortcutAction::VolumeDown => client.volume_down().await,
+        ShortcutAction::Mute => client.toggle_mute().await,
+    };
+
+    if let Err(e) = result {
+        report_vibin_api_error(app_handle, e);
+    }
+}
+
+fn report_shortcut_error(app_handle: &tauri::AppHandle, message: String) -> String {
+    warn!("Shortcut error: {}", message);
+
+    app_handle
+        .emit_all(&WeeVibinMessage::Error.to_string(), AppError {
+            category: AppErrorCategory::Shortcut,
+            message: message.clone(),
+        })
+        .unwrap();
+
+    message
+}
+
+/// Register a global shortcut with the OS. This fails if the accelerator is invalid, or is already
+/// registered (possibly by another application).
+fn register_shortcut(
+    app_handle: &tauri::AppHandle,
+    action: ShortcutAction,
+    accelerator: &str,
+) -> Result<(), String> {
+    let handler_app_handle = app_handle.clone();
+
+    app_handle
+        .global_shortcut_manager()
+        .register(accelerator, move || {
+            let app_handle = handler_app_handle.clone();
+
+            tauri::async_runtime::spawn(async move {
+                perform_shortcut_action(action, &app_handle).await;
+            });
+        })
+        .map_err(|e| format!("Could not register {accelerator} for {action}: {e}"))
+}
+
+fn register_shortcuts(app_handle: &tauri::AppHandle, bindings: &ShortcutBindings) {
+    for binding in &bindings.bindings {
+        if let Some(accelerator) = &binding.accelerator {
+            if let Err(e) = register_shortcut(app_handle, binding.action, accelerator) {
+                report_shortcut_error(app_handle, e);
+            }
+        }
+    }
+}
+
+#[tauri::command]
+fn get_shortcut_bindings(
+    shortcut_bindings: tauri::State<'_, ShortcutBindingsMutex>,
+) -> ShortcutBindings {
+    shortcut_bindings.inner().lock().unwrap().clone()
+}
+
+/// Bind a global shortcut action to an accelerator (e.g. "CmdOrCtrl+Alt+P"), or unbind it when
+/// `accelerator` is None. Conflicts (with another action, or with a shortcut registered by another
+/// application) are reported as errors, and leave the existing binding in place.
+#[tauri::command]
+async fn set_shortcut_binding(
+    action: ShortcutAction,
+    accelerator: Option<String>,
+    shortcut_bindings: tauri::State<'_, ShortcutBindingsMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<ShortcutBindings, String> {
+    let mut bindings = shortcut_bindings.inner().lock().unwrap();
+
+    let mut updated = bindings.clone();
+    updated.set(action, accelerator).map_err(|e| report_shortcut_error(&app_handle, e))?;
+
+    let previous = bindings.accelerator(action).cloned();
+    let accelerator = updated.accelerator(action).cloned();
+
+    if previous == accelerator {
+        return Ok(updated);
+    }
+
+    if let Some(previous) = &previous {
+        if let Err(e) = app_handle.global_shortcut_manager().unregister(previous) {
+            warn!("Could not unregister {} for {}: {}", previous, action, e);
+        }
+    }
+
+    if let Some(accelerator) = &accelerator {
+        if let Err(e) = register_shortcut(&app_handle, action, accelerator) {
+            if let Some(previous) = &previous {
+                let _ = register_shortcut(&app_handle, action, previous);
+            }
+
+            return Err(report_shortcut_error(&app_handle, e));
+        }
+    }
+
+    save_to_store(&app_handle, SHORTCUT_BINDINGS_KEY, &updated)?;
+    *bindings = updated.clone();
+
+    Ok(updated)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Track change notifications (see TrackNotifier). The settings are persisted in the store.
+
+#[tauri::command]
+fn get_notification_settings(
+    settings: tauri::State<'_, NotificationSettingsMutex>,
+) -> NotificationSettings {
+    settings.inner().lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn set_notification_settings(
+    notification_settings: NotificationSettings,
+    settings: tauri::State<'_, NotificationSettingsMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<NotificationSettings, String> {
+    save_to_store(&app_handle, NOTIFICATION_SETTINGS_KEY, &notification_settings)?;
+    *settings.inner().lock().unwrap() = notification_settings.clone();
+
+    Ok(notification_settings)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Scrobbling (see Scrobbler). The settings are persisted in the store.
+
+#[tauri::command]
+fn get_scrobble_settings(settings: tauri::State<'_, ScrobbleSettingsMutex>) -> ScrobbleSettings {
+    settings.inner().lock().unwrap().clone()
+}
+
+/// Persist the scrobble settings, and submit any listens which were queued (e.g. because the
+/// previous token was invalid).
+#[tauri::command]
+fn set_scrobble_settings(
+    scrobble_settings: ScrobbleSettings,
+    settings: tauri::State<'_, ScrobbleSettingsMutex>,
+    scrobbler: tauri::State<'_, Arc<Scrobbler>>,
+    app_handle: tauri::AppHandle,
+) -> Result<ScrobbleSettings, String> {
+    save_to_store(&app_handle, SCROBBLE_SETTINGS_KEY, &scrobble_settings)?;
+    *settings.inner().lock().unwrap() = scrobble_settings.clone();
+    scrobbler.submit_queued();
+
+    Ok(scrobble_settings)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Listening history and stats (see ListeningHistory and listening_stats).
+
+/// A page of the listening history, most recent first, optionally limited to entries whose title,
+/// artist, or album contain `search`.
+#[tauri::command]
+fn get_listening_history(
+    offset: usize,
+    limit: usize,
+    search: Option<String>,
+    history: tauri::State<'_, ListeningHistoryRef>,
+) -> Result<HistoryPage, String> {
+    history
+        .page(offset, limit, search.as_deref())
+        .map_err(|e| format!("Could not read the listening history: {e}"))
+}
+
+/// Listening statistics (top artists, listening time per day, etc) for the period ending now.
+/// `top_limit` is the length of each top list.
+#[tauri::command]
+fn get_listening_stats(
+    period: StatsPeriod,
+    top_limit: Option<usize>,
+    history: tauri::State<'_, ListeningHistoryRef>,
+) -> Result<ListeningStats, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default();
+
+    listening_stats(&history, period, top_limit, now)
+        .map_err(|e| format!("Could not compute listening stats: {e}"))
+}
+
+// ------------------------------------------------------------------------------------------------
+// The local HTTP control API (see ControlApi). The settings are persisted in the store.
+
+#[tauri::command]
+fn get_control_api_settings(
+    settings: tauri::State<'_, ControlApiSettingsMutex>,
+) -> ControlApiSettings {
+    settings.inner().lock().unwrap().clone()
+}
+
+/// Persist the control API settings, and start, stop, or restart the server to match. If the
+/// server can't be started with the new settings (e.g. the port is in use), the previous settings
+/// are kept.
+#[tauri::command]
+async fn set_control_api_settings(
+    control_api_settings: ControlApiSettings,
+    settings: tauri::State<'_, ControlApiSettingsMutex>,
+    control_api: tauri::State<'_, Arc<ControlApi>>,
+    app_handle: tauri::AppHandle,
+) -> Result<ControlApiSettings, String> {
+    let previous = settings.inner().lock().unwrap().clone();
+
+    if let Err(e) = control_api.inner().apply(&control_api_settings).await {
+        if let Err(e) = control_api.inner().apply(&previous).await {
+            warn!("Could not restore the control API: {}", e);
+        }
+
+        return Err(e);
+    }
+
+    save_to_store(&app_handle, CONTROL_API_SETTINGS_KEY, &control_api_settings)?;
+    *settings.inner().lock().unwrap() = control_api_settings.clone();
+
+    Ok(control_api_settings)
+}
+
 fn main() {
     let app_state: AppStateMutex = Arc::new(Mutex::new(AppState::new()));
     let vibin_state: VibinStateMutex = Arc::new(Mutex::new(VibinState::new()));
+    let vibin_api: VibinApiClientMutex = Arc::new(Mutex::new(VibinApiClient::new()));
 
     let context = tauri::generate_context!();
 
     // Runtime state
     let app_state_clone = Arc::clone(&app_state);
     let vibin_state_clone = Arc::clone(&vibin_state);
+    let vibin_api_clone = Arc::clone(&vibin_api);
 
-    // Configure the system tray
-    let quit = CustomMenuItem::new("quit".to_string(), "Quit WeeVibin");
-    let system_tray_menu = SystemTrayMenu::new().add_item(quit);
-
-    let system_tray = SystemTray::new().with_menu(system_tray_menu);
+    // Configure the system tray. The menu is updated as the Vibin state changes (see TrayMenu).
+    let system_tray = SystemTray::new().with_menu(build_tray_menu(&TrayMenuState::default()));
 
     tauri::Builder::default()
         .setup(move |app| {
             info!("Application data directory: {:?}", app.handle().path_resolver().app_data_dir().unwrap());
 
-            let ws_manager_mutex = Arc::new(TauriMutex::new(WebSocketManager::new(
+            let tray_menu = Arc::new(TrayMenu::new(app.app_handle()));
+            let tray_status = Arc::new(TrayStatusDisplay::new(app.app_handle()));
+
+            let notification_settings: NotificationSettingsMutex = Arc::new(Mutex::new(
+                load_from_store(&app.app_handle(), NOTIFICATION_SETTINGS_KEY),
+            ));
+            let track_notifier =
+                Arc::new(TrackNotifier::new(app.app_handle(), notification_settings.clone()));
+
+            let scrobble_settings: ScrobbleSettingsMutex = Arc::new(Mutex::new(
+                load_from_store(&app.app_handle(), SCROBBLE_SETTINGS_KEY),
+            ));
+            let scrobble_queue_path = app
+                .path_resolver()
+                .app_data_dir()
+                .unwrap_or_default()
+                .join("scrobble-queue.json");
+            let scrobbler = Arc::new(Scrobbler::new(scrobble_settings.clone(), scrobble_queue_path));
+            scrobbler.retry_queued();
+
+            let history_path =
+                app.path_resolver().app_data_dir().unwrap_or_default().join(HISTORY_DATABASE);
+            let history: ListeningHistoryRef = Arc::new(
+                ListeningHistory::open(&history_path).unwrap_or_else(|e| {
+                    warn!("Could not open the listening history {:?}: {:?}", history_path, e);
+                    ListeningHistory::open_in_memory().expect("Could not create listening history")
+                }),
+            );
+            let history_recorder = Arc::new(HistoryRecorder::new(history.clone()));
+
+            // The settings are saved straight away, so the token generated for a new install (see
+            // ControlApiSettings) stays the same from one launch to the next.
+            let control_api_settings: ControlApiSettings =
+                load_from_store(&app.app_handle(), CONTROL_API_SETTINGS_KEY);
+            if let Err(e) =
+                save_to_store(&app.app_handle(), CONTROL_API_SETTINGS_KEY, &control_api_settings)
+            {
+                warn!("Could not save the control API settings: {}", e);
+            }
+            let control_api = Arc::new(ControlApi::new(
+                app_state_clone.clone(),
+                vibin_state_clone.clone(),
+                vibin_api_clone,
+            ));
+
+            let startup_control_api = control_api.clone();
+            let startup_control_api_settings = control_api_settings.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = startup_control_api.apply(&startup_control_api_settings).await {
+                    warn!("Could not start the control API: {}", e);
+                }
+            });
+
+            // Everything which follows the Vibin connection is its own sink.
+            let sink: StateSinkRef = Arc::new(CompositeStateSink::new(vec![
+                Arc::new(TauriStateSink::new(app.app_handle())),
+                tray_menu.clone(),
+                tray_status.clone(),
+                track_notifier.clone(),
+                scrobbler.clone(),
+                history_recorder.clone(),
+                control_api.clone(),
+            ]));
+
+            let reconnect_policy: ReconnectPolicy =
+                load_from_store(&app.app_handle(), RECONNECT_POLICY_KEY);
+
+            let mut ws_manager = WebSocketManager::new(
                 None,
                 Arc::new(Mutex::new(false)),
+                app_state_clone.clone(),
+                vibin_state_clone.clone(),
+                sink.clone(),
+            );
+            ws_manager.reconnect_policy = reconnect_policy.clone();
+            let ws_manager_mutex = Arc::new(TauriMutex::new(ws_manager));
+
+            // Multi-room mode rooms forward the selected room's state to the same global state
+            // and sink as the (single) WebSocket manager.
+            let mut room_manager = RoomManager::new(
+                sink,
                 app_state_clone,
                 vibin_state_clone,
-                app.app_handle(),
-            )));
+                Arc::new(TauriRoomEventSink::new(app.app_handle())),
+            );
+            room_manager.set_reconnect_policy(reconnect_policy);
+            let room_manager_mutex: RoomManagerMutex = Arc::new(TauriMutex::new(room_manager));
+
+            let server_profiles: ServerProfiles =
+                load_from_store(&app.app_handle(), SERVER_PROFILES_KEY);
+            tray_menu.update_profiles(&server_profiles);
+
+            let shortcut_bindings = load_from_store::<ShortcutBindings>(
+                &app.app_handle(),
+                SHORTCUT_BINDINGS_KEY,
+            )
+            .with_defaults();
+            register_shortcuts(&app.app_handle(), &shortcut_bindings);
 
             app.manage(ws_manager_mutex);
+            app.manage(room_manager_mutex);
+            app.manage(tray_menu);
+            app.manage(tray_status);
+            app.manage(track_notifier);
+            app.manage(notification_settings);
+            app.manage(scrobbler);
+            app.manage(scrobble_settings);
+            app.manage(history);
+            app.manage(history_recorder);
+            app.manage(control_api);
+            app.manage::<ControlApiSettingsMutex>(Arc::new(Mutex::new(control_api_settings)));
+            app.manage::<ServerProfilesMutex>(Arc::new(Mutex::new(server_profiles)));
+            app.manage::<ShortcutBindingsMutex>(Arc::new(Mutex::new(shortcut_bindings)));
 
             // Hide the WeeVibin icon in the macOS dock
             //
@@ -121,6 +1117,7 @@ fn main() {
         })
         .manage(app_state)
         .manage(vibin_state)
+        .manage(vibin_api)
         .plugin(tauri_plugin_log::Builder::default()
             .targets(LOG_TARGETS)
             .format(|out, message, record| {
@@ -179,9 +1176,45 @@ fn main() {
                 }
                 SystemTrayEvent::MenuItemClick { id, .. } => {
                     match id.as_str() {
-                        "quit" => {
+                        QUIT_ID => {
+                            app.state::<Arc<HistoryRecorder>>().flush();
                             std::process::exit(0);
                         }
+                        PLAY_PAUSE_ID | NEXT_ID | PREVIOUS_ID | MUTE_ID | POWER_ID => {
+                            let item_id = id.clone();
+                            let app_handle = app.app_handle();
+
+                            tauri::async_runtime::spawn(async move {
+                                perform_tray_action(&item_id, &app_handle).await;
+                            });
+                        }
+                        id if id.starts_with(SOURCE_ID_PREFIX) => {
+                            let source_id = id[SOURCE_ID_PREFIX.len()..].to_string();
+                            let app_handle = app.app_handle();
+
+                            tauri::async_runtime::spawn(async move {
+                                let vibin_api = app_handle.state::<VibinApiClientMutex>();
+                                let vibin_state = app_handle.state::<VibinStateMutex>();
+
+                                // Failures are reported to the UI by set_source().
+                                let _ = set_source(
+                                    &source_id,
+                                    vibin_api.inner(),
+                                    vibin_state.inner(),
+                                    &app_handle,
+                                ).await;
+                            });
+                        }
+                        id if id.starts_with(PROFILE_ID_PREFIX) => {
+                            let name = id[PROFILE_ID_PREFIX.len()..].to_string();
+                            let app_handle = app.app_handle();
+
+                            tauri::async_runtime::spawn(async move {
+                                if let Err(e) = switch_to_server_profile(&name, &app_handle).await {
+                                    warn!("Could not switch to server profile {:?}: {}", name, e);
+                                }
+                            });
+                        }
                         _ => {}
                     }
                 }
@@ -197,14 +1230,52 @@ fn main() {
             }
             _ => {}
         })
-        .invoke_handler(tauri::generate_handler![on_ui_ready, set_vibin_server])
+        .invoke_handler(tauri::generate_handler![
+            on_ui_ready,
+            set_vibin_server,
+            send_vibin_command,
+            set_session_recording,
+            replay_vibin_session,
+            vibin_power,
+            vibin_transport,
+            vibin_seek,
+            vibin_toggle_mute,
+            vibin_volume_up,
+            vibin_volume_down,
+            vibin_volume_set,
+            vibin_set_source,
+            vibin_queue_play,
+            vibin_queue_delete,
+            vibin_queue_move,
+            open_in_vibinui,
+            get_server_profiles,
+            save_server_profile,
+            delete_server_profile,
+            switch_server_profile,
+            set_multi_room_mode,
+            get_rooms,
+            get_shortcut_bindings,
+            set_shortcut_binding,
+            get_notification_settings,
+            set_notification_settings,
+            get_scrobble_settings,
+            set_scrobble_settings,
+            get_listening_history,
+            get_listening_stats,
+            get_control_api_settings,
+            set_control_api_settings,
+        ])
         .build(context)
         .expect("Error while building WeeVibin")
-        .run(|_app_handle, event| match event {
+        .run(|app_handle, event| match event {
             // Keep the Rust backend running in the background
             tauri::RunEvent::ExitRequested { api, .. } => {
                 api.prevent_exit();
             }
+            // Record the play in progress before exiting.
+            tauri::RunEvent::Exit => {
+                app_handle.state::<Arc<HistoryRecorder>>().flush();
+            }
             _ => {}
         });
 }
@@ -0,0 +1,400 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::api::notification::Notification;
+use tauri::{AppHandle, Manager, Runtime, Wry};
+
+use crate::sink::StateSink;
+use crate::state::{ActiveTrack, SourceClass, VibinState};
+
+// ===============================================================================================
+// Desktop notifications for track changes. A notification shows the track's title, artist, and
+// album, along with its album art (where the platform supports notification images). The art is
+// downloaded once into an on-disk cache (see ArtCache), as notifications need a local image.
+//
+// Notifications are not shown while the popover (the main window) is visible, as the track is
+// already on screen; and each track is notified at most once, however many times its details are
+// repeated by Vibin. Notifications can be turned off altogether, or just for some source classes
+// (e.g. internet radio, which changes track without much interest). These settings are persisted
+// in the application's store (see main.rs) under NOTIFICATION_SETTINGS_KEY.
+// ===============================================================================================
+
+pub const NOTIFICATION_SETTINGS_KEY: &str = "notification-settings";
+
+const ART_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+const ART_CACHE_MAX_FILES: usize = 200;
+const ART_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    /// Sources of these classes never notify.
+    pub muted_source_classes: Vec<SourceClass>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            enabled: false,
+            muted_source_classes: vec![SourceClass::StreamRadio],
+        }
+    }
+}
+
+impl NotificationSettings {
+    pub fn is_muted(&self, source_class: Option<&SourceClass>) -> bool {
+        !self.enabled
+            || source_class.is_some_and(|class| self.muted_source_classes.contains(class))
+    }
+}
+
+pub type NotificationSettingsMutex = Arc<Mutex<NotificationSettings>>;
+
+/// The notification title and body for a track, or None if the track has no title.
+pub fn notification_text(active_track: &ActiveTrack) -> Option<(String, String)> {
+    let title = active_track.title.clone().filter(|title| !title.is_empty())?;
+
+    let body = [&active_track.artist, &active_track.album]
+        .iter()
+        .filter_map(|detail| detail.as_deref())
+        .filter(|detail| !detail.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" — ");
+
+    Some((title, body))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+struct TrackNotifierState {
+    source_class: Option<SourceClass>,
+    last_track: Option<ActiveTrack>,
+}
+
+impl TrackNotifierState {
+    /// Whether a track change warrants a notification. Every track is remembered whether it's
+    /// notified or not, so a track which was suppressed (e.g. while the popover was visible)
+    /// isn't notified later on when its details are repeated.
+    fn track_changed(
+        &mut self,
+        active_track: &ActiveTrack,
+        settings: &NotificationSettings,
+        is_popover_visible: bool,
+    ) -> bool {
+        let is_repeat = self
+            .last_track
+            .as_ref()
+            .is_some_and(|last_track| last_track.is_same_track(active_track));
+
+        self.last_track = Some(active_track.clone());
+
+        !is_repeat
+            && !is_popover_visible
+            && !settings.is_muted(self.source_class.as_ref())
+            && notification_text(active_track).is_some()
+    }
+}
+
+/// Shows a desktop notification when the track changes.
+pub struct TrackNotifier<R: Runtime = Wry> {
+    app_handle: AppHandle<R>,
+    settings: NotificationSettingsMutex,
+    art_cache: Option<Arc<ArtCache>>,
+    state: Mutex<TrackNotifierState>,
+}
+
+impl<R: Runtime> TrackNotifier<R> {
+    pub fn new(app_handle: AppHandle<R>, settings: NotificationSettingsMutex) -> Self {
+        let art_cache = app_handle
+            .path_resolver()
+            .app_cache_dir()
+            .map(|cache_dir| Arc::new(ArtCache::new(cache_dir.join("art"))));
+
+        TrackNotifier {
+            app_handle,
+            settings,
+            art_cache,
+            state: Mutex::new(TrackNotifierState::default()),
+        }
+    }
+}
+
+impl<R: Runtime> StateSink for TrackNotifier<R> {
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.state.lock().unwrap().source_class =
+            vibin_state.source.as_ref().and_then(|source| source.class.clone());
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        let is_popover_visible = self
+            .app_handle
+            .get_window("main")
+            .and_then(|window| window.is_visible().ok())
+            .unwrap_or(false);
+
+        let settings = self.settings.lock().unwrap().clone();
+
+        if !self.state.lock().unwrap().track_changed(active_track, &settings, is_popover_visible) {
+            return;
+        }
+
+        let Some((title, body)) = notification_text(active_track) else {
+            return;
+        };
+
+        let identifier = self.app_handle.config().tauri.bundle.identifier.clone();
+        let art_cache = self.art_cache.clone();
+        let art_url = active_track.art_url.clone();
+
+        tauri::async_runtime::spawn(async move {
+            let mut notification = Notification::new(identifier).title(title).body(body);
+
+            if let (Some(art_cache), Some(art_url)) = (art_cache, art_url) {
+                match art_cache.get(&art_url).await {
+                    Ok(art_path) => {
+                        notification = notification.icon(art_path.to_string_lossy());
+                    }
+                    Err(e) => warn!("Could not cache album art for notification: {}", e),
+                }
+            }
+
+            if let Err(e) = notification.show() {
+                warn!("Could not show track notification: {:?}", e);
+            }
+        });
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Album art downloaded to a local directory, keyed on its URL. The directory holds at most
+/// ART_CACHE_MAX_FILES images; the least recently downloaded are removed first.
+pub struct ArtCache {
+    directory: PathBuf,
+    client: reqwest::Client,
+}
+
+impl ArtCache {
+    pub fn new(directory: PathBuf) -> Self {
+        ArtCache { directory, client: reqwest::Client::new() }
+    }
+
+    /// The local path of the art at `url`, downloading it if it isn't already cached.
+    pub async fn get(&self, url: &str) -> Result<PathBuf, String> {
+        let path = self.directory.join(ArtCache::file_name(url));
+
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(path);
+        }
+
+        let art = self
+            .client
+            .get(url)
+            .timeout(ART_DOWNLOAD_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Could not download {url}: {e}"))?
+            .bytes()
+            .await
+            .map_err(|e| format!("Could not download {url}: {e}"))?;
+
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| format!("Could not create {:?}: {e}", self.directory))?;
+
+        // Write then rename, so a partially-written file is never taken to be cached art.
+        let partial_path = path.with_extension("partial");
+        tokio::fs::write(&partial_path, &art)
+            .await
+            .map_err(|e| format!("Could not write {:?}: {e}", partial_path))?;
+        tokio::fs::rename(&partial_path, &path)
+            .await
+            .map_err(|e| format!("Could not write {:?}: {e}", path))?;
+
+        if let Err(e) = ArtCache::prune(&self.directory, ART_CACHE_MAX_FILES) {
+            warn!("Could not prune the album art cache: {}", e);
+        }
+
+        Ok(path)
+    }
+
+    fn file_name(url: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+
+        let extension = Path::new(url.split(['?', '#']).next().unwrap_or_default())
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .filter(|extension| ART_EXTENSIONS.contains(&extension.as_str()))
+            .unwrap_or(String::from("jpg"));
+
+        format!("{:016x}.{extension}", hasher.finish())
+    }
+
+    fn prune(directory: &Path, max_files: usize) -> std::io::Result<()> {
+        let mut files = std::fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect::<Vec<_>>();
+
+        if files.len() <= max_files {
+            return Ok(());
+        }
+
+        files.sort();
+
+        for (_, path) in files.iter().take(files.len() - max_files) {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::notifications::{
+        notification_text, ArtCache, NotificationSettings, TrackNotifierState,
+    };
+    use crate::state::{ActiveTrack, SourceClass};
+
+    fn active_track(title: &str, track_media_id: &str) -> ActiveTrack {
+        ActiveTrack {
+            title: Some(title.into()),
+            artist: Some("Nils Frahm".into()),
+            album: Some("Spaces".into()),
+            art_url: None,
+            duration: Some(300),
+            album_media_id: None,
+            track_media_id: Some(track_media_id.into()),
+            stream_url: None,
+        }
+    }
+
+    #[test]
+    fn it_notifies_each_track_once() {
+        let settings = NotificationSettings { enabled: true, muted_source_classes: vec![] };
+        let mut state = TrackNotifierState::default();
+
+        assert!(state.track_changed(&active_track("Says", "1"), &settings, false));
+        assert!(!state.track_changed(&active_track("Says", "1"), &settings, false));
+        assert!(state.track_changed(&active_track("Hammers", "2"), &settings, false));
+
+        // A track first seen while the popover is visible isn't notified once it's hidden.
+        assert!(!state.track_changed(&active_track("Over There", "3"), &settings, true));
+        assert!(!state.track_changed(&active_track("Over There", "3"), &settings, false));
+    }
+
+    #[test]
+    fn it_does_not_notify_muted_sources() {
+        let mut state = TrackNotifierState::default();
+
+        assert!(!state.track_changed(
+            &active_track("Says", "1"),
+            &NotificationSettings::default(),
+            false
+        ));
+
+        let settings = NotificationSettings {
+            enabled: true,
+            muted_source_classes: vec![SourceClass::StreamRadio],
+        };
+
+        state.source_class = Some(SourceClass::StreamRadio);
+        assert!(!state.track_changed(&active_track("Hammers", "2"), &settings, false));
+
+        state.source_class = Some(SourceClass::StreamMedia);
+        assert!(state.track_changed(&active_track("Over There", "3"), &settings, false));
+    }
+
+    #[test]
+    fn it_builds_notification_text() {
+        let mut track = active_track("Says", "1");
+
+        assert_eq!(
+            notification_text(&track),
+            Some((String::from("Says"), String::from("Nils Frahm — Spaces")))
+        );
+
+        track.album = None;
+        assert_eq!(
+            notification_text(&track),
+            Some((String::from("Says"), String::from("Nils Frahm")))
+        );
+
+        track.title = None;
+        assert_eq!(notification_text(&track), None);
+    }
+
+    #[tokio::test]
+    async fn it_downloads_album_art_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                requests_clone.fetch_add(1, Ordering::SeqCst);
+
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nart")
+                    .await;
+            }
+        });
+
+        let directory = env::temp_dir().join(format!("weevibin-art-{}", std::process::id()));
+        let art_cache = ArtCache::new(directory.clone());
+        let url = format!("http://127.0.0.1:{port}/art/cover.PNG?size=large");
+
+        let path = art_cache.get(&url).await.unwrap();
+        assert_eq!(path.extension().unwrap(), "png");
+        assert_eq!(std::fs::read(&path).unwrap(), b"art");
+
+        assert_eq!(art_cache.get(&url).await.unwrap(), path);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_prunes_the_oldest_album_art() {
+        let directory = env::temp_dir().join(format!("weevibin-art-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            std::fs::write(directory.join(name), name).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        ArtCache::prune(&directory, 2).unwrap();
+
+        assert!(!directory.join("a.jpg").exists());
+        assert!(directory.join("b.jpg").exists());
+        assert!(directory.join("c.jpg").exists());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
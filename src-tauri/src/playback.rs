@@ -0,0 +1,372 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state::{ActiveTrack, AudioFormat, PlayState, SourceClass, VibinState};
+
+// ===============================================================================================
+// Follows what's being played, from the tracks (CurrentlyPlaying), playhead positions (Position),
+// and play state (TransportState) seen on the Vibin WebSocket connection. This is the basis for
+// scrobbling (see scrobble.rs) and the listening history (see history.rs).
+//
+// Only time spent playing counts towards a play: the playhead moving while paused, or jumping
+// forward when seeking, doesn't. A play counts as "listened" once it has been played for half the
+// track's duration, or for MAX_LISTEN_THRESHOLD_SECS (whichever comes first); this is the usual
+// scrobbling rule. A play which ends before then counts as skipped.
+//
+// A play ends when the track changes, when playback is stopped (not paused), when the streamer
+// goes into standby, or when the play is ended explicitly (e.g. the connection was lost). Playing
+// the same track again after a stop or standby is a new play.
+// ===============================================================================================
+
+const MAX_LISTEN_THRESHOLD_SECS: isize = 4 * 60;
+
+// Positions arrive about once a second. A bigger step forward is taken to be a seek.
+const MAX_POSITION_STEP_SECS: isize = 5;
+
+/// One play of a track.
+#[derive(Debug, Clone)]
+pub struct Play {
+    pub track: ActiveTrack,
+    pub source_class: Option<SourceClass>,
+    pub audio_format: Option<AudioFormat>,
+    /// When the track started playing, in seconds since the epoch.
+    pub started_at: i64,
+    /// How long the track has been played for (not including seeks or pauses).
+    pub played_secs: isize,
+}
+
+impl Play {
+    /// How long the track needs to be played for to count as listened.
+    pub fn listen_threshold(&self) -> isize {
+        self.track
+            .duration
+            .filter(|duration| *duration > 0)
+            .map(|duration| (duration / 2).min(MAX_LISTEN_THRESHOLD_SECS))
+            .unwrap_or(MAX_LISTEN_THRESHOLD_SECS)
+    }
+
+    pub fn is_listened(&self) -> bool {
+        self.played_secs >= self.listen_threshold()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PlaybackEvent {
+    /// The track started playing.
+    Started(Play),
+    /// The track has been played for long enough to count as listened.
+    Listened(Play),
+    /// The play ended (see the top of this file).
+    Ended(Play),
+}
+
+struct CurrentPlay {
+    play: Play,
+    last_position: Option<isize>,
+    is_started: bool,
+    is_listened: bool,
+}
+
+#[derive(Default)]
+pub struct PlaybackTracker {
+    current: Option<CurrentPlay>,
+    /// The latest track, which is played again (as a new play) if playback resumes after the
+    /// current play has ended.
+    track: Option<ActiveTrack>,
+    is_playing: bool,
+    is_streamer_on: bool,
+    source_class: Option<SourceClass>,
+    audio_format: Option<AudioFormat>,
+}
+
+impl PlaybackTracker {
+    pub fn new() -> Self {
+        PlaybackTracker::default()
+    }
+
+    pub fn track_changed(&mut self, active_track: &ActiveTrack) -> Vec<PlaybackEvent> {
+        let mut events: Vec<PlaybackEvent> = self.end_play().into_iter().collect();
+
+        self.track = Some(active_track.clone());
+        self.start_play(None);
+
+        events.extend(self.events());
+        events
+    }
+
+    /// Follow the play state, source, audio format, and streamer power. The audio format arrives
+    /// after the track change, so the current play takes on the latest one.
+    pub fn vibin_state_changed(&mut self, vibin_state: &VibinState) -> Vec<PlaybackEvent> {
+        let was_streamer_on = self.is_streamer_on;
+
+        self.is_playing = vibin_state.is_playing();
+        self.is_streamer_on = vibin_state.is_streamer_power_on();
+        self.source_class = vibin_state.source.as_ref().and_then(|source| source.class.clone());
+        self.audio_format = vibin_state.audio_format.clone();
+
+        let is_stopped = vibin_state
+            .transport
+            .as_ref()
+            .is_some_and(|transport| transport.play_state == Some(PlayState::Stop));
+
+        // The streamer's power is compared with what it was, as it's unknown (off) until Vibin has
+        // reported it.
+        if is_stopped || (was_streamer_on && !self.is_streamer_on) {
+            return self.end_play().into_iter().collect();
+        }
+
+        if let Some(current) = self.current.as_mut() {
+            current.play.source_class = self.source_class.clone();
+            current.play.audio_format = self.audio_format.clone();
+        }
+
+        self.events()
+    }
+
+    pub fn position_changed(&mut self, position: isize) -> Vec<PlaybackEvent> {
+        if let Some(current) = self.current.as_mut() {
+            let step = current.last_position.map(|last_position| position - last_position);
+
+            if self.is_playing {
+                if let Some(step @ 1..=MAX_POSITION_STEP_SECS) = step {
+                    current.play.played_secs += step;
+                }
+            }
+
+            current.last_position = Some(position);
+        }
+
+        self.events()
+    }
+
+    /// End the current play, if any, and forget the track (e.g. when the connection was lost, or
+    /// weevibin is quitting). Nothing more is followed until the next track change.
+    pub fn end(&mut self) -> Option<PlaybackEvent> {
+        self.track = None;
+        self.is_playing = false;
+        self.is_streamer_on = false;
+
+        self.end_play()
+    }
+
+    fn start_play(&mut self, audio_format: Option<AudioFormat>) {
+        self.current = self.track.as_ref().map(|track| CurrentPlay {
+            play: Play {
+                track: track.clone(),
+                source_class: self.source_class.clone(),
+                audio_format,
+                started_at: unix_time(),
+                played_secs: 0,
+            },
+            last_position: None,
+            is_started: false,
+            is_listened: false,
+        });
+    }
+
+    fn end_play(&mut self) -> Option<PlaybackEvent> {
+        self.current.take().map(|ended| PlaybackEvent::Ended(ended.play))
+    }
+
+    fn events(&mut self) -> Vec<PlaybackEvent> {
+        let mut events = Vec::new();
+
+        // Playback has resumed since the last play ended.
+        if self.current.is_none() && self.is_playing {
+            self.start_play(self.audio_format.clone());
+        }
+
+        let Some(current) = self.current.as_mut() else {
+            return events;
+        };
+
+        if self.is_playing && !current.is_started {
+            current.is_started = true;
+            events.push(PlaybackEvent::Started(current.play.clone()));
+        }
+
+        if !current.is_listened && current.play.is_listened() {
+            current.is_listened = true;
+            events.push(PlaybackEvent::Listened(current.play.clone()));
+        }
+
+        events
+    }
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::playback::{PlaybackEvent, PlaybackTracker};
+    use crate::state::{ActiveTrack, PlayState, PowerState, TransportState, VibinState};
+
+    fn active_track(title: &str, duration: Option<isize>) -> ActiveTrack {
+        ActiveTrack {
+            title: Some(title.into()),
+            artist: Some("Hania Rani".into()),
+            album: Some("Home".into()),
+            art_url: None,
+            duration,
+            album_media_id: None,
+            track_media_id: None,
+            stream_url: None,
+        }
+    }
+
+    fn vibin_state(play_state: PlayState) -> VibinState {
+        let mut vibin_state = VibinState::new();
+        vibin_state.transport = Some(TransportState {
+            play_state: Some(play_state),
+            active_controls: Vec::new(),
+            repeat: None,
+            shuffle: None,
+        });
+
+        vibin_state
+    }
+
+    fn listened(events: Vec<PlaybackEvent>) -> usize {
+        events.iter().filter(|event| matches!(event, PlaybackEvent::Listened(_))).count()
+    }
+
+    #[test]
+    fn it_starts_and_listens_after_half_the_track() {
+        let mut tracker = PlaybackTracker::new();
+
+        assert!(tracker.track_changed(&active_track("Leaving", Some(10))).is_empty());
+
+        let events = tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::Started(play)] if play.track.title.as_deref() == Some("Leaving")
+        ));
+
+        for position in 0..5 {
+            assert_eq!(listened(tracker.position_changed(position)), 0);
+        }
+
+        let events = tracker.position_changed(5);
+        assert!(matches!(&events[..], [PlaybackEvent::Listened(play)] if play.played_secs == 5));
+
+        // Each play is listened once.
+        assert!(tracker.position_changed(6).is_empty());
+    }
+
+    #[test]
+    fn it_listens_to_long_tracks_after_four_minutes() {
+        let mut tracker = PlaybackTracker::new();
+        tracker.track_changed(&active_track("Glass", Some(3600)));
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+
+        let listens: usize =
+            (0..=240).map(|position| listened(tracker.position_changed(position))).sum();
+
+        assert_eq!(listens, 1);
+    }
+
+    #[test]
+    fn it_only_counts_time_spent_playing() {
+        let mut tracker = PlaybackTracker::new();
+        tracker.track_changed(&active_track("Eden", Some(20)));
+
+        // Seeking forward doesn't count, and nor does the playhead moving while paused.
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        tracker.position_changed(0);
+        tracker.position_changed(9);
+        tracker.vibin_state_changed(&vibin_state(PlayState::Pause));
+        tracker.position_changed(10);
+
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        let listens: usize =
+            (11..=19).map(|position| listened(tracker.position_changed(position))).sum();
+
+        assert_eq!(listens, 0);
+        assert_eq!(listened(tracker.position_changed(20)), 1);
+    }
+
+    #[test]
+    fn it_ends_the_previous_play() {
+        let mut tracker = PlaybackTracker::new();
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        tracker.track_changed(&active_track("Hello", Some(300)));
+
+        for position in 0..=30 {
+            tracker.position_changed(position);
+        }
+
+        let events = tracker.track_changed(&active_track("Nest", Some(300)));
+
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::Ended(ended), PlaybackEvent::Started(started)]
+                if ended.played_secs == 30
+                    && !ended.is_listened()
+                    && started.track.title.as_deref() == Some("Nest")
+        ));
+    }
+
+    #[test]
+    fn it_ends_the_play_when_playback_stops_and_starts_a_new_one_when_resumed() {
+        let mut tracker = PlaybackTracker::new();
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        tracker.track_changed(&active_track("Hello", Some(300)));
+
+        for position in 0..=10 {
+            tracker.position_changed(position);
+        }
+
+        // Pausing doesn't end the play, but stopping does.
+        assert!(tracker.vibin_state_changed(&vibin_state(PlayState::Pause)).is_empty());
+
+        let events = tracker.vibin_state_changed(&vibin_state(PlayState::Stop));
+        assert!(matches!(&events[..], [PlaybackEvent::Ended(ended)] if ended.played_secs == 10));
+        assert!(tracker.position_changed(11).is_empty());
+
+        let events = tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::Started(started)]
+                if started.played_secs == 0 && started.track.title.as_deref() == Some("Hello")
+        ));
+    }
+
+    #[test]
+    fn it_ends_the_play_when_the_streamer_goes_into_standby() {
+        let mut streamer_on = vibin_state(PlayState::Play);
+        streamer_on.streamer_power = Some(PowerState::On);
+        let mut standby = vibin_state(PlayState::Play);
+        standby.streamer_power = Some(PowerState::Unknown("standby".into()));
+
+        let mut tracker = PlaybackTracker::new();
+        tracker.vibin_state_changed(&streamer_on);
+        tracker.track_changed(&active_track("Hello", Some(300)));
+        tracker.position_changed(0);
+        tracker.position_changed(1);
+
+        let events = tracker.vibin_state_changed(&standby);
+        assert!(matches!(&events[..], [PlaybackEvent::Ended(ended)] if ended.played_secs == 1));
+    }
+
+    #[test]
+    fn it_ends_the_play_on_request() {
+        let mut tracker = PlaybackTracker::new();
+        tracker.vibin_state_changed(&vibin_state(PlayState::Play));
+        tracker.track_changed(&active_track("Hello", Some(300)));
+        tracker.position_changed(0);
+        tracker.position_changed(1);
+
+        let ended = tracker.end();
+        assert!(matches!(ended, Some(PlaybackEvent::Ended(ended)) if ended.played_secs == 1));
+        assert!(tracker.end().is_none());
+
+        // Nothing is followed until the next track change.
+        assert!(tracker.vibin_state_changed(&vibin_state(PlayState::Play)).is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+// ===============================================================================================
+// Named Vibin server profiles, e.g. one per room. Each profile holds a Vibin WebSocket URL; the
+// active profile is the one weevibin connects to. Profiles are persisted in the application's
+// store (see main.rs) under SERVER_PROFILES_KEY.
+// ===============================================================================================
+
+pub const SERVER_PROFILES_KEY: &str = "server-profiles";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerProfile {
+    pub name: String,
+    /// Vibin WebSocket URL, e.g. ws://vibin.local:8080/ws
+    pub host: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerProfiles {
+    pub profiles: Vec<ServerProfile>,
+    pub active: Option<String>,
+}
+
+impl ServerProfiles {
+    pub fn get(&self, name: &str) -> Option<&ServerProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn active_profile(&self) -> Option<&ServerProfile> {
+        self.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    /// Add a profile, or replace the existing profile with the same name.
+    pub fn save(&mut self, profile: ServerProfile) -> Result<(), String> {
+        if profile.name.trim().is_empty() {
+            return Err(String::from("Profile name cannot be empty"));
+        }
+
+        match self.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+
+        Ok(())
+    }
+
+    /// Remove a profile. Removing the active profile leaves no profile active.
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let count = self.profiles.len();
+        self.profiles.retain(|profile| profile.name != name);
+
+        if self.profiles.len() == count {
+            return Err(format!("Unknown profile: {name}"));
+        }
+
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+
+        Ok(())
+    }
+
+    /// Make the named profile the active one, returning it.
+    pub fn activate(&mut self, name: &str) -> Result<ServerProfile, String> {
+        let profile = self.get(name).cloned().ok_or(format!("Unknown profile: {name}"))?;
+        self.active = Some(profile.name.clone());
+
+        Ok(profile)
+    }
+}
+
+pub type ServerProfilesMutex = Arc<Mutex<ServerProfiles>>;
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::profiles::{ServerProfile, ServerProfiles};
+
+    fn profile(name: &str, host: &str) -> ServerProfile {
+        ServerProfile { name: name.into(), host: host.into() }
+    }
+
+    #[test]
+    fn it_saves_and_replaces_profiles() {
+        let mut profiles = ServerProfiles::default();
+
+        profiles.save(profile("Lounge", "ws://lounge:8080/ws")).unwrap();
+        profiles.save(profile("Study", "ws://study:8080/ws")).unwrap();
+        profiles.save(profile("Lounge", "ws://lounge.local:8080/ws")).unwrap();
+
+        assert_eq!(profiles.profiles.len(), 2);
+        assert_eq!(profiles.get("Lounge").unwrap().host, "ws://lounge.local:8080/ws");
+        assert!(profiles.save(profile(" ", "ws://nowhere:8080/ws")).is_err());
+    }
+
+    #[test]
+    fn it_activates_and_deletes_profiles() {
+        let mut profiles = ServerProfiles::default();
+        profiles.save(profile("Lounge", "ws://lounge:8080/ws")).unwrap();
+
+        assert!(profiles.activate("Kitchen").is_err());
+        assert_eq!(profiles.activate("Lounge").unwrap().host, "ws://lounge:8080/ws");
+        assert_eq!(profiles.active_profile().map(|profile| profile.name.as_str()), Some("Lounge"));
+
+        profiles.delete("Lounge").unwrap();
+
+        assert_eq!(profiles.active, None);
+        assert!(profiles.delete("Lounge").is_err());
+    }
+}
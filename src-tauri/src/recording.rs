@@ -0,0 +1,231 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+
+// Recording of raw Vibin WebSocket sessions, for reproducing problems after the fact.
+//
+// A recording is a JSONL file with one RecordedEntry per line. Each entry holds a received
+// WebSocket message along with when it was received, both relative to the start of the recording
+// (for replaying with the original timing) and as a wall-clock timestamp (for correlating with
+// logs and user reports).
+
+pub const RECORDINGS_DIR: &str = "recordings";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedClose {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data")]
+pub enum RecordedMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<RecordedClose>),
+}
+
+impl RecordedMessage {
+    /// Returns None for raw frames, which are never returned when reading a WebSocket stream.
+    pub fn from_message(message: &tungstenite::Message) -> Option<Self> {
+        match message {
+            tungstenite::Message::Text(text) => Some(RecordedMessage::Text(text.clone())),
+            tungstenite::Message::Binary(data) => Some(RecordedMessage::Binary(data.clone())),
+            tungstenite::Message::Ping(data) => Some(RecordedMessage::Ping(data.clone())),
+            tungstenite::Message::Pong(data) => Some(RecordedMessage::Pong(data.clone())),
+            tungstenite::Message::Close(frame) => Some(RecordedMessage::Close(
+                frame.as_ref().map(|frame| RecordedClose {
+                    code: frame.code.into(),
+                    reason: frame.reason.to_string(),
+                }),
+            )),
+            tungstenite::Message::Frame(_) => None,
+        }
+    }
+
+    pub fn into_message(self) -> tungstenite::Message {
+        match self {
+            RecordedMessage::Text(text) => tungstenite::Message::Text(text),
+            RecordedMessage::Binary(data) => tungstenite::Message::Binary(data),
+            RecordedMessage::Ping(data) => tungstenite::Message::Ping(data),
+            RecordedMessage::Pong(data) => tungstenite::Message::Pong(data),
+            RecordedMessage::Close(close) => tungstenite::Message::Close(close.map(|close| {
+                CloseFrame {
+                    code: CloseCode::from(close.code),
+                    reason: close.reason.into(),
+                }
+            })),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEntry {
+    /// Milliseconds since the recording started.
+    pub elapsed_ms: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub message: RecordedMessage,
+}
+
+fn epoch_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct SessionRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl SessionRecorder {
+    /// Create a new recording file in `directory`, named for the current time.
+    pub fn create(directory: &Path) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        let path = directory.join(format!("session-{}.jsonl", epoch_millis()));
+        let file = File::create(&path)?;
+
+        Ok(SessionRecorder {
+            path,
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, message: &tungstenite::Message) -> io::Result<()> {
+        let Some(message) = RecordedMessage::from_message(message) else {
+            return Ok(());
+        };
+
+        let entry = RecordedEntry {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            timestamp_ms: epoch_millis(),
+            message,
+        };
+
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+
+        // Flush every entry so the recording is complete even if weevibin exits unexpectedly.
+        self.writer.flush()
+    }
+}
+
+/// Read all the entries from a recording. Lines which can't be parsed are skipped.
+pub fn read_recording(path: &Path) -> io::Result<Vec<RecordedEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<RecordedEntry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping unreadable line {} in {:?}: {:?}", index + 1, path, e),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Resolve `recording` (a file name, or a path) to a recording in `recordings_dir`. Anything
+/// outside `recordings_dir` (including through symlinks or "..") is rejected.
+pub fn resolve_recording(recordings_dir: &Path, recording: &Path) -> Result<PathBuf, String> {
+    let recordings_dir = recordings_dir
+        .canonicalize()
+        .map_err(|e| format!("Could not find recordings directory {:?}: {}", recordings_dir, e))?;
+    let path = recordings_dir
+        .join(recording)
+        .canonicalize()
+        .map_err(|e| format!("Could not find recording {:?}: {}", recording, e))?;
+
+    match path.starts_with(&recordings_dir) && path.is_file() {
+        true => Ok(path),
+        false => Err(format!("Not a recording in {:?}: {:?}", recordings_dir, recording)),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use std::path::Path;
+
+    use crate::recording::{read_recording, resolve_recording, RecordedMessage, SessionRecorder};
+
+    #[test]
+    fn it_round_trips_messages() {
+        let directory = env::temp_dir().join(format!("weevibin-recording-{}", std::process::id()));
+        let messages = vec![
+            tungstenite::Message::Text(r#"{"type": "Position", "payload": {"position": 1}}"#.into()),
+            tungstenite::Message::Ping(vec![1, 2, 3]),
+            tungstenite::Message::Close(None),
+        ];
+
+        let mut recorder = SessionRecorder::create(&directory).unwrap();
+
+        for message in &messages {
+            recorder.record(message).unwrap();
+        }
+
+        let entries = read_recording(recorder.path()).unwrap();
+        let replayed: Vec<tungstenite::Message> =
+            entries.iter().map(|entry| entry.message.clone().into_message()).collect();
+
+        assert_eq!(replayed, messages);
+        assert!(entries.windows(2).all(|pair| pair[0].elapsed_ms <= pair[1].elapsed_ms));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_ignores_raw_frames() {
+        let frame = tungstenite::protocol::frame::Frame::ping(vec![]);
+
+        assert_eq!(RecordedMessage::from_message(&tungstenite::Message::Frame(frame)), None);
+    }
+
+    #[test]
+    fn it_only_resolves_recordings_in_the_recordings_directory() {
+        let directory = env::temp_dir().join(format!("weevibin-resolve-{}", std::process::id()));
+        let recordings_dir = directory.join("recordings");
+        std::fs::create_dir_all(&recordings_dir).unwrap();
+        std::fs::write(recordings_dir.join("session.jsonl"), "").unwrap();
+        std::fs::write(directory.join("outside.jsonl"), "").unwrap();
+
+        let resolved = resolve_recording(&recordings_dir, Path::new("session.jsonl")).unwrap();
+        assert_eq!(resolved, recordings_dir.canonicalize().unwrap().join("session.jsonl"));
+        assert_eq!(resolve_recording(&recordings_dir, &resolved).unwrap(), resolved);
+
+        assert!(resolve_recording(&recordings_dir, Path::new("../outside.jsonl")).is_err());
+        assert!(resolve_recording(&recordings_dir, &directory.join("outside.jsonl")).is_err());
+        assert!(resolve_recording(&recordings_dir, Path::new("missing.jsonl")).is_err());
+        assert!(resolve_recording(&recordings_dir, Path::new(".")).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
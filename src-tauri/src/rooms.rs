@@ -0,0 +1,364 @@
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::async_runtime::Mutex as TauriMutex;
+use tauri::{AppHandle, Manager, Runtime, Wry};
+
+use crate::backoff::ReconnectPolicy;
+use crate::profiles::ServerProfile;
+use crate::sink::{SinkEvent, StateSink, StateSinkRef};
+use crate::state::{
+    ActiveTrack, AppError, AppState, AppStateMutex, Position, VibinConnectionState, VibinState,
+    VibinStateMutex, WeeVibinMessage,
+};
+use crate::websocket::{VibinCommand, WebSocketManager};
+
+// ===============================================================================================
+// Multi-room mode. Every server profile is a "room", and each room has its own Vibin WebSocket
+// connection with its own AppState and VibinState. Everything a room's connection emits is
+// tagged with the room id (the profile name) and sent to a RoomEventSink.
+//
+// One room is the "selected" room. The selected room's state is also copied into the global
+// AppState/VibinState and emitted to the global StateSink, so everything which isn't room-aware
+// (the main UI screens, the tray, the REST API commands) follows the selected room.
+// ===============================================================================================
+
+pub type RoomId = String;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomSummary {
+    pub id: RoomId,
+    pub host: String,
+    pub vibin_connection: VibinConnectionState,
+    pub selected: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomEvent {
+    pub room_id: RoomId,
+    pub event: SinkEvent,
+}
+
+pub trait RoomEventSink: Send + Sync {
+    fn emit_room_event(&self, room_event: &RoomEvent);
+    fn emit_rooms(&self, rooms: &[RoomSummary]);
+}
+
+pub type RoomEventSinkRef = Arc<dyn RoomEventSink>;
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct TauriRoomEventSink<R: Runtime = Wry> {
+    app_handle: AppHandle<R>,
+}
+
+impl<R: Runtime> TauriRoomEventSink<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
+        TauriRoomEventSink { app_handle }
+    }
+}
+
+impl<R: Runtime> RoomEventSink for TauriRoomEventSink<R> {
+    fn emit_room_event(&self, room_event: &RoomEvent) {
+        self.app_handle.emit_all(&WeeVibinMessage::RoomEvent.to_string(), room_event).unwrap();
+    }
+
+    fn emit_rooms(&self, rooms: &[RoomSummary]) {
+        self.app_handle.emit_all(&WeeVibinMessage::Rooms.to_string(), rooms).unwrap();
+    }
+}
+
+/// Keeps all room events in memory, for tests and headless use.
+#[derive(Default)]
+pub struct RecordingRoomEventSink {
+    room_events: Mutex<Vec<RoomEvent>>,
+}
+
+impl RecordingRoomEventSink {
+    pub fn new() -> Self {
+        RecordingRoomEventSink::default()
+    }
+
+    /// All events emitted by the given room so far, oldest first.
+    pub fn events_for(&self, room_id: &str) -> Vec<SinkEvent> {
+        self.room_events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|room_event| room_event.room_id == room_id)
+            .map(|room_event| room_event.event.clone())
+            .collect()
+    }
+}
+
+impl RoomEventSink for RecordingRoomEventSink {
+    fn emit_room_event(&self, room_event: &RoomEvent) {
+        self.room_events.lock().unwrap().push(room_event.clone());
+    }
+
+    fn emit_rooms(&self, _rooms: &[RoomSummary]) {}
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// The selected room, and where the selected room's state is forwarded to.
+struct RoomSelection {
+    selected: Mutex<Option<RoomId>>,
+    sink: StateSinkRef,
+    app_state_mutex: AppStateMutex,
+    vibin_state_mutex: VibinStateMutex,
+}
+
+impl RoomSelection {
+    fn is_selected(&self, room_id: &str) -> bool {
+        self.selected.lock().unwrap().as_deref() == Some(room_id)
+    }
+}
+
+/// The StateSink given to each room's WebSocketManager.
+struct RoomStateSink {
+    room_id: RoomId,
+    room_events: RoomEventSinkRef,
+    selection: Arc<RoomSelection>,
+}
+
+impl RoomStateSink {
+    fn emit(&self, event: SinkEvent, forward: impl FnOnce(&RoomSelection)) {
+        self.room_events.emit_room_event(&RoomEvent { room_id: self.room_id.clone(), event });
+
+        // The selection lock is held while forwarding, so a room which is being deselected can't
+        // overwrite the state of the newly-selected room.
+        let selected = self.selection.selected.lock().unwrap();
+
+        if selected.as_deref() == Some(self.room_id.as_str()) {
+            forward(&self.selection);
+        }
+    }
+}
+
+impl StateSink for RoomStateSink {
+    fn emit_app_state(&self, app_state: &AppState) {
+        self.emit(SinkEvent::AppState(app_state.clone()), |selection| {
+            *selection.app_state_mutex.lock().unwrap() = app_state.clone();
+            selection.sink.emit_app_state(app_state);
+        });
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.emit(SinkEvent::VibinState(Box::new(vibin_state.clone())), |selection| {
+            *selection.vibin_state_mutex.lock().unwrap() = vibin_state.clone();
+            selection.sink.emit_vibin_state(vibin_state);
+        });
+    }
+
+    fn emit_position(&self, position: &Position) {
+        self.emit(SinkEvent::Position(position.clone()), |selection| {
+            selection.sink.emit_position(position);
+        });
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        self.emit(SinkEvent::TrackChange(active_track.clone()), |selection| {
+            selection.sink.emit_track_change(active_track);
+        });
+    }
+
+    fn emit_error(&self, error: &AppError) {
+        self.emit(SinkEvent::Error(error.clone()), |selection| {
+            selection.sink.emit_error(error);
+        });
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+struct Room {
+    id: RoomId,
+    host: String,
+    manager: WebSocketManager,
+}
+
+pub struct RoomManager {
+    rooms: Vec<Room>,
+    selection: Arc<RoomSelection>,
+    room_events: RoomEventSinkRef,
+    reconnect_policy: ReconnectPolicy,
+}
+
+impl RoomManager {
+    /// `sink`, `app_state_mutex`, and `vibin_state_mutex` are the global (single-connection)
+    /// sink and state, which follow the selected room.
+    pub fn new(
+        sink: StateSinkRef,
+        app_state_mutex: AppStateMutex,
+        vibin_state_mutex: VibinStateMutex,
+        room_events: RoomEventSinkRef,
+    ) -> Self {
+        RoomManager {
+            rooms: vec![],
+            selection: Arc::new(RoomSelection {
+                selected: Mutex::new(None),
+                sink,
+                app_state_mutex,
+                vibin_state_mutex,
+            }),
+            room_events,
+            reconnect_policy: ReconnectPolicy::default(),
+        }
+    }
+
+    /// The reconnect policy for rooms connected from now on.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
+    }
+
+    /// Multi-room mode is active whenever there are rooms.
+    pub fn is_active(&self) -> bool {
+        !self.rooms.is_empty()
+    }
+
+    pub fn selected_room(&self) -> Option<RoomId> {
+        self.selection.selected.lock().unwrap().clone()
+    }
+
+    pub fn rooms(&self) -> Vec<RoomSummary> {
+        self.rooms
+            .iter()
+            .map(|room| {
+                let vibin_connection =
+                    room.manager.app_state_mutex.lock().unwrap().vibin_connection.clone();
+
+                RoomSummary {
+                    id: room.id.clone(),
+                    host: room.host.clone(),
+                    vibin_connection,
+                    selected: self.selection.is_selected(&room.id),
+                }
+            })
+            .collect()
+    }
+
+    /// Make the rooms match the given profiles: rooms are connected for new profiles, and
+    /// disconnected for removed profiles. A room whose profile host has changed is reconnected.
+    pub async fn sync_rooms(&mut self, profiles: &[ServerProfile]) {
+        let stale: Vec<RoomId> = self
+            .rooms
+            .iter()
+            .filter(|room| {
+                !profiles.iter().any(|profile| profile.name == room.id && profile.host == room.host)
+            })
+            .map(|room| room.id.clone())
+            .collect();
+
+        for room_id in stale {
+            self.stop_room(&room_id).await;
+        }
+
+        for profile in profiles {
+            if !self.rooms.iter().any(|room| room.id == profile.name) {
+                self.start_room(&profile.name, &profile.host);
+            }
+        }
+
+        self.emit_rooms();
+    }
+
+    /// Disconnect from every room, leaving multi-room mode.
+    pub async fn stop_all(&mut self) {
+        self.sync_rooms(&[]).await;
+    }
+
+    /// Select the room the controls (and the global state) follow. Returns the room's host.
+    pub fn select_room(&self, room_id: &str) -> Result<String, String> {
+        let room = self
+            .rooms
+            .iter()
+            .find(|room| room.id == room_id)
+            .ok_or(format!("Unknown room: {room_id}"))?;
+
+        info!("Selecting room {:?}: {}", room.id, room.host);
+
+        *self.selection.selected.lock().unwrap() = Some(room.id.clone());
+
+        // Bring the global state up to date with the room. This is done after updating the
+        // selection, so any newer room state emitted in the meantime will also be forwarded.
+        let app_state = room.manager.app_state_mutex.lock().unwrap().clone();
+        let vibin_state = room.manager.vibin_state_mutex.lock().unwrap().clone();
+
+        {
+            let selected = self.selection.selected.lock().unwrap();
+
+            if selected.as_deref() == Some(room_id) {
+                *self.selection.app_state_mutex.lock().unwrap() = app_state.clone();
+                *self.selection.vibin_state_mutex.lock().unwrap() = vibin_state.clone();
+                self.selection.sink.emit_app_state(&app_state);
+                self.selection.sink.emit_vibin_state(&vibin_state);
+            }
+        }
+
+        self.emit_rooms();
+
+        Ok(room.host.clone())
+    }
+
+    /// Send a command to Vibin in the selected room.
+    pub fn send_command(&self, command: &VibinCommand) -> Result<(), String> {
+        let selected = self.selected_room().ok_or("No room selected")?;
+
+        match self.rooms.iter().find(|room| room.id == selected) {
+            Some(room) => room.manager.send_command(command),
+            None => Err(format!("Unknown room: {selected}")),
+        }
+    }
+
+    fn start_room(&mut self, room_id: &str, host: &str) {
+        info!("Connecting room {:?}: {}", room_id, host);
+
+        let sink = RoomStateSink {
+            room_id: room_id.to_string(),
+            room_events: self.room_events.clone(),
+            selection: self.selection.clone(),
+        };
+
+        let mut manager = WebSocketManager::new(
+            Some(Box::new(host.to_string())),
+            Arc::new(Mutex::new(false)),
+            Arc::new(Mutex::new(AppState::new())),
+            Arc::new(Mutex::new(VibinState::new())),
+            Arc::new(sink),
+        );
+
+        manager.reconnect_policy = self.reconnect_policy.clone();
+
+        // Keep trying rooms which aren't available yet (e.g. a streamer which is unplugged).
+        manager.set_startup_retry(true);
+        manager.start();
+
+        self.rooms.push(Room { id: room_id.to_string(), host: host.to_string(), manager });
+    }
+
+    async fn stop_room(&mut self, room_id: &str) {
+        let Some(index) = self.rooms.iter().position(|room| room.id == room_id) else {
+            warn!("Cannot stop unknown room: {:?}", room_id);
+            return;
+        };
+
+        info!("Disconnecting room {:?}", room_id);
+
+        let mut room = self.rooms.remove(index);
+        room.manager.stop().await;
+
+        let mut selected = self.selection.selected.lock().unwrap();
+
+        if selected.as_deref() == Some(room_id) {
+            *selected = None;
+        }
+    }
+
+    fn emit_rooms(&self) {
+        self.room_events.emit_rooms(&self.rooms());
+    }
+}
+
+pub type RoomManagerMutex = Arc<TauriMutex<RoomManager>>;
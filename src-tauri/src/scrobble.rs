@@ -0,0 +1,555 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::async_runtime::Mutex as TauriMutex;
+use tokio::time::sleep;
+
+use crate::playback::{Play, PlaybackEvent, PlaybackTracker};
+use crate::sink::StateSink;
+use crate::state::{ActiveTrack, Position, VibinState};
+
+// ===============================================================================================
+// Scrobbling to a ListenBrainz-compatible server (ListenBrainz itself, or e.g. Maloja or Koito,
+// which implement its submission API).
+//
+// A PlaybackTracker (see playback.rs) follows what's being played, and decides when to submit:
+//
+// "playing now" - when a track starts playing. These are informational, so they're not retried.
+// "listen" - once a track has been played for half its duration, or for four minutes (whichever
+//   comes first). Only time spent playing counts; seeking doesn't.
+//
+// Listens which can't be submitted (e.g. the server is unreachable) are queued in a file, and
+// resubmitted before the next listen or every RETRY_INTERVAL. The settings (server URL and user
+// token) are persisted in the application's store (see main.rs) under SCROBBLE_SETTINGS_KEY.
+// ===============================================================================================
+
+pub const SCROBBLE_SETTINGS_KEY: &str = "scrobble-settings";
+
+const DEFAULT_API_URL: &str = "https://api.listenbrainz.org";
+const SUBMISSION_CLIENT: &str = "weevibin";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// ListenBrainz accepts up to 1000 listens per submission. Smaller batches keep each request well
+// within what compatible servers (e.g. Maloja, Koito) handle, and mean a rejected or timed out
+// request only requeues a small part of a large backlog.
+const MAX_LISTENS_PER_SUBMISSION: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScrobbleSettings {
+    pub enabled: bool,
+    /// The root of the ListenBrainz API, e.g. https://api.listenbrainz.org
+    pub api_url: String,
+    /// The user's ListenBrainz token.
+    pub token: String,
+}
+
+impl Default for ScrobbleSettings {
+    fn default() -> Self {
+        ScrobbleSettings {
+            enabled: false,
+            api_url: String::from(DEFAULT_API_URL),
+            token: String::new(),
+        }
+    }
+}
+
+impl ScrobbleSettings {
+    fn is_active(&self) -> bool {
+        self.enabled && !self.api_url.trim().is_empty() && !self.token.trim().is_empty()
+    }
+}
+
+pub type ScrobbleSettingsMutex = Arc<Mutex<ScrobbleSettings>>;
+
+// -----------------------------------------------------------------------------------------------
+
+/// A track as submitted to ListenBrainz. `listened_at` (seconds since the epoch) is when the
+/// track started playing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listen {
+    pub listened_at: i64,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub duration: Option<isize>,
+}
+
+impl Listen {
+    /// Tracks without an artist and title can't be scrobbled.
+    fn from_play(play: &Play) -> Option<Listen> {
+        let present = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+
+        Some(Listen {
+            listened_at: play.started_at,
+            artist: present(&play.track.artist)?,
+            title: present(&play.track.title)?,
+            album: present(&play.track.album),
+            duration: play.track.duration.filter(|duration| *duration > 0),
+        })
+    }
+
+    fn track_metadata(&self) -> serde_json::Value {
+        let mut additional_info = json!({ "submission_client": SUBMISSION_CLIENT });
+
+        if let Some(duration) = self.duration {
+            additional_info["duration_ms"] = json!(duration * 1000);
+        }
+
+        let mut track_metadata = json!({
+            "artist_name": self.artist,
+            "track_name": self.title,
+            "additional_info": additional_info,
+        });
+
+        if let Some(album) = &self.album {
+            track_metadata["release_name"] = json!(album);
+        }
+
+        track_metadata
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScrobbleError {
+    /// The server couldn't be reached, or is temporarily unable to accept submissions.
+    Unavailable(String),
+    /// The server refused the submission.
+    Rejected { status: u16, message: String },
+}
+
+impl ScrobbleError {
+    /// Whether the submission might succeed later. An invalid token is worth retrying, as the
+    /// user can fix it.
+    fn is_retryable(&self) -> bool {
+        match self {
+            ScrobbleError::Unavailable(_) => true,
+            ScrobbleError::Rejected { status, .. } => *status == 401,
+        }
+    }
+}
+
+impl fmt::Display for ScrobbleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScrobbleError::Unavailable(e) => write!(f, "Scrobble server unavailable: {e}"),
+            ScrobbleError::Rejected { status, message } => {
+                write!(f, "Scrobble server responded with HTTP {status}: {message}")
+            }
+        }
+    }
+}
+
+/// Submits listens, queueing them in `queue_path` when they can't be submitted.
+pub struct ListenSubmitter {
+    http: Client,
+    queue_path: PathBuf,
+    // Serializes access to the queue file.
+    queue_lock: TauriMutex<()>,
+}
+
+impl ListenSubmitter {
+    pub fn new(queue_path: PathBuf) -> Self {
+        ListenSubmitter {
+            http: Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default(),
+            queue_path,
+            queue_lock: TauriMutex::new(()),
+        }
+    }
+
+    pub async fn submit_playing_now(
+        &self,
+        settings: &ScrobbleSettings,
+        listen: &Listen,
+    ) -> Result<(), ScrobbleError> {
+        let submission = json!({
+            "listen_type": "playing_now",
+            "payload": [{ "track_metadata": listen.track_metadata() }],
+        });
+
+        self.post(settings, &submission).await
+    }
+
+    /// Queue a listen, then submit everything in the queue.
+    pub async fn submit_listen(&self, settings: &ScrobbleSettings, listen: Listen) {
+        let _queue_lock = self.queue_lock.lock().await;
+
+        let mut queue = self.load_queue().await;
+        queue.push(listen);
+
+        self.submit_queue(settings, queue).await;
+    }
+
+    /// Submit any queued listens.
+    pub async fn flush(&self, settings: &ScrobbleSettings) {
+        let _queue_lock = self.queue_lock.lock().await;
+
+        let queue = self.load_queue().await;
+
+        if !queue.is_empty() {
+            self.submit_queue(settings, queue).await;
+        }
+    }
+
+    /// The listens waiting to be submitted, oldest first.
+    pub async fn queued(&self) -> Vec<Listen> {
+        let _queue_lock = self.queue_lock.lock().await;
+
+        self.load_queue().await
+    }
+
+    async fn submit_queue(&self, settings: &ScrobbleSettings, mut queue: Vec<Listen>) {
+        while !queue.is_empty() {
+            let batch_size = queue.len().min(MAX_LISTENS_PER_SUBMISSION);
+            let batch = &queue[..batch_size];
+
+            let submission = json!({
+                "listen_type": if batch.len() == 1 { "single" } else { "import" },
+                "payload": batch
+                    .iter()
+                    .map(|listen| json!({
+                        "listened_at": listen.listened_at,
+                        "track_metadata": listen.track_metadata(),
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+
+            match self.post(settings, &submission).await {
+                Ok(()) => info!("Scrobbled {} listen(s)", batch_size),
+                Err(e) if e.is_retryable() => {
+                    warn!("Could not scrobble; {} listen(s) queued: {}", queue.len(), e);
+                    break;
+                }
+                Err(e) => warn!("Dropping {} listen(s) refused by the server: {}", batch_size, e),
+            }
+
+            queue.drain(..batch_size);
+        }
+
+        self.save_queue(&queue).await;
+    }
+
+    async fn post(
+        &self,
+        settings: &ScrobbleSettings,
+        submission: &serde_json::Value,
+    ) -> Result<(), ScrobbleError> {
+        let url = format!("{}/1/submit-listens", settings.api_url.trim().trim_end_matches('/'));
+
+        let response = self
+            .http
+            .post(url)
+            .header("Authorization", format!("Token {}", settings.token.trim()))
+            .json(submission)
+            .send()
+            .await
+            .map_err(|e| ScrobbleError::Unavailable(e.to_string()))?;
+
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let message = response.text().await.unwrap_or_default();
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => Err(ScrobbleError::Unavailable(message)),
+            status if status.is_server_error() => Err(ScrobbleError::Unavailable(message)),
+            status => Err(ScrobbleError::Rejected { status: status.as_u16(), message }),
+        }
+    }
+
+    async fn load_queue(&self) -> Vec<Listen> {
+        match tokio::fs::read(&self.queue_path).await {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable scrobble queue {:?}: {:?}", self.queue_path, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    async fn save_queue(&self, queue: &[Listen]) {
+        let result = match queue.is_empty() {
+            true => match tokio::fs::remove_file(&self.queue_path).await {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+            false => {
+                if let Some(directory) = self.queue_path.parent() {
+                    let _ = tokio::fs::create_dir_all(directory).await;
+                }
+
+                tokio::fs::write(&self.queue_path, serde_json::to_vec(queue).unwrap()).await
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("Could not save the scrobble queue {:?}: {:?}", self.queue_path, e);
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Scrobbles what the WebSocket connection sees.
+pub struct Scrobbler {
+    settings: ScrobbleSettingsMutex,
+    tracker: Mutex<PlaybackTracker>,
+    submitter: Arc<ListenSubmitter>,
+}
+
+impl Scrobbler {
+    pub fn new(settings: ScrobbleSettingsMutex, queue_path: PathBuf) -> Self {
+        Scrobbler {
+            settings,
+            tracker: Mutex::new(PlaybackTracker::new()),
+            submitter: Arc::new(ListenSubmitter::new(queue_path)),
+        }
+    }
+
+    /// Resubmit any queued listens now (e.g. after the settings have changed).
+    pub fn submit_queued(&self) {
+        let settings = self.settings.lock().unwrap().clone();
+
+        if !settings.is_active() {
+            return;
+        }
+
+        let submitter = self.submitter.clone();
+
+        tauri::async_runtime::spawn(async move {
+            submitter.flush(&settings).await;
+        });
+    }
+
+    /// Resubmit any queued listens every RETRY_INTERVAL.
+    pub fn retry_queued(self: &Arc<Self>) {
+        let scrobbler = self.clone();
+
+        tauri::async_runtime::spawn(async move {
+            loop {
+                sleep(RETRY_INTERVAL).await;
+                scrobbler.submit_queued();
+            }
+        });
+    }
+
+    fn submit(&self, events: Vec<PlaybackEvent>) {
+        let settings = self.settings.lock().unwrap().clone();
+
+        if events.is_empty() || !settings.is_active() {
+            return;
+        }
+
+        let submitter = self.submitter.clone();
+
+        tauri::async_runtime::spawn(async move {
+            for event in events {
+                match event {
+                    PlaybackEvent::Started(play) => {
+                        let Some(listen) = Listen::from_play(&play) else {
+                            continue;
+                        };
+
+                        if let Err(e) = submitter.submit_playing_now(&settings, &listen).await {
+                            info!("Could not submit playing now: {}", e);
+                        }
+                    }
+                    PlaybackEvent::Listened(play) => {
+                        if let Some(listen) = Listen::from_play(&play) {
+                            submitter.submit_listen(&settings, listen).await;
+                        }
+                    }
+                    PlaybackEvent::Ended(_) => {}
+                }
+            }
+        });
+    }
+}
+
+impl StateSink for Scrobbler {
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        let events = self.tracker.lock().unwrap().vibin_state_changed(vibin_state);
+        self.submit(events);
+    }
+
+    fn emit_position(&self, position: &Position) {
+        let events = self.tracker.lock().unwrap().position_changed(position.position);
+        self.submit(events);
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        let events = self.tracker.lock().unwrap().track_changed(active_track);
+        self.submit(events);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::playback::Play;
+    use crate::scrobble::{Listen, ListenSubmitter, ScrobbleSettings};
+    use crate::state::ActiveTrack;
+
+    fn play(title: &str, artist: Option<&str>) -> Play {
+        Play {
+            track: ActiveTrack {
+                title: Some(title.into()),
+                artist: artist.map(String::from),
+                album: Some("Home".into()),
+                art_url: None,
+                duration: Some(200),
+                album_media_id: None,
+                track_media_id: None,
+                stream_url: None,
+            },
+            source_class: None,
+            audio_format: None,
+            started_at: 1700000000,
+            played_secs: 100,
+        }
+    }
+
+    fn listen(title: &str) -> Listen {
+        Listen {
+            listened_at: 1700000000,
+            artist: String::from("Hania Rani"),
+            title: title.into(),
+            album: None,
+            duration: Some(200),
+        }
+    }
+
+    /// Accepts HTTP requests, responding with `status` and recording each request body.
+    async fn scrobble_server(status: u16) -> (u16, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+
+                // Read the headers, then the body (of Content-Length bytes).
+                let body_start = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+
+                    if let Some(index) = request.windows(4).position(|window| window == b"\r\n\r\n")
+                    {
+                        break index + 4;
+                    }
+                };
+
+                let headers = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+                let content_length: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|length| length.trim().parse().unwrap())
+                    .unwrap_or(0);
+
+                while request.len() < body_start + content_length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+
+                requests_clone
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&request[body_start..]).unwrap());
+
+                let response = format!(
+                    "HTTP/1.1 {status} OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}"
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (port, requests)
+    }
+
+    #[test]
+    fn it_only_scrobbles_tracks_with_an_artist_and_title() {
+        let listen = Listen::from_play(&play("Leaving", Some("Hania Rani"))).unwrap();
+
+        assert_eq!(listen.listened_at, 1700000000);
+        assert_eq!(listen.album.as_deref(), Some("Home"));
+        assert_eq!(Listen::from_play(&play("Station ID", None)), None);
+        assert_eq!(Listen::from_play(&play("", Some("Hania Rani"))), None);
+    }
+
+    #[tokio::test]
+    async fn it_queues_listens_while_the_server_is_unreachable() {
+        let queue_path = env::temp_dir()
+            .join(format!("weevibin-scrobble-{}", std::process::id()))
+            .join("scrobble-queue.json");
+        let submitter = ListenSubmitter::new(queue_path.clone());
+
+        // Nothing listens on port 9, so the listens are queued.
+        let mut settings = ScrobbleSettings {
+            enabled: true,
+            api_url: String::from("http://127.0.0.1:9"),
+            token: String::from("token"),
+        };
+
+        submitter.submit_listen(&settings, listen("Hello")).await;
+        submitter.submit_listen(&settings, listen("Nest")).await;
+
+        assert_eq!(submitter.queued().await, vec![listen("Hello"), listen("Nest")]);
+        assert!(queue_path.exists());
+
+        let (port, requests) = scrobble_server(200).await;
+        settings.api_url = format!("http://127.0.0.1:{port}/");
+        submitter.flush(&settings).await;
+
+        assert!(submitter.queued().await.is_empty());
+        assert!(!queue_path.exists());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["listen_type"], "import");
+        assert_eq!(requests[0]["payload"][1]["track_metadata"]["track_name"], "Nest");
+        assert_eq!(requests[0]["payload"][1]["listened_at"], 1700000000);
+
+        std::fs::remove_dir_all(queue_path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_drops_listens_refused_by_the_server() {
+        let queue_path = env::temp_dir()
+            .join(format!("weevibin-scrobble-refused-{}", std::process::id()))
+            .join("scrobble-queue.json");
+        let submitter = ListenSubmitter::new(queue_path);
+
+        let (port, requests) = scrobble_server(400).await;
+        let settings = ScrobbleSettings {
+            enabled: true,
+            api_url: format!("http://127.0.0.1:{port}"),
+            token: String::from("token"),
+        };
+
+        submitter.submit_listen(&settings, listen("Hello")).await;
+
+        assert!(submitter.queued().await.is_empty());
+        assert_eq!(requests.lock().unwrap()[0]["listen_type"], "single");
+    }
+}
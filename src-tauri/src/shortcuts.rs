@@ -0,0 +1,217 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+// ===============================================================================================
+// Global keyboard shortcuts (including hardware media keys where the platform allows). Each
+// action can be bound to one accelerator (e.g. "CmdOrCtrl+Alt+Up" or "MediaPlayPause"), and no
+// two actions can share an accelerator. Bindings are persisted in the application's store (see
+// main.rs) under SHORTCUT_BINDINGS_KEY, and are registered with the OS by main.rs.
+// ===============================================================================================
+
+pub const SHORTCUT_BINDINGS_KEY: &str = "shortcut-bindings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+impl ShortcutAction {
+    pub const ALL: [ShortcutAction; 6] = [
+        ShortcutAction::PlayPause,
+        ShortcutAction::Next,
+        ShortcutAction::Previous,
+        ShortcutAction::VolumeUp,
+        ShortcutAction::VolumeDown,
+        ShortcutAction::Mute,
+    ];
+
+    fn default_accelerator(&self) -> &'static str {
+        match self {
+            ShortcutAction::PlayPause => "MediaPlayPause",
+            ShortcutAction::Next => "MediaTrackNext",
+            ShortcutAction::Previous => "MediaTrackPrevious",
+            ShortcutAction::VolumeUp => "CmdOrCtrl+Alt+Up",
+            ShortcutAction::VolumeDown => "CmdOrCtrl+Alt+Down",
+            ShortcutAction::Mute => "CmdOrCtrl+Alt+M",
+        }
+    }
+}
+
+impl fmt::Display for ShortcutAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ShortcutAction::PlayPause => "play/pause",
+            ShortcutAction::Next => "next",
+            ShortcutAction::Previous => "previous",
+            ShortcutAction::VolumeUp => "volume up",
+            ShortcutAction::VolumeDown => "volume down",
+            ShortcutAction::Mute => "mute",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShortcutBinding {
+    pub action: ShortcutAction,
+    /// None when the action has no shortcut.
+    pub accelerator: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShortcutBindings {
+    pub bindings: Vec<ShortcutBinding>,
+}
+
+impl Default for ShortcutBindings {
+    fn default() -> Self {
+        ShortcutBindings {
+            bindings: ShortcutAction::ALL
+                .iter()
+                .map(|action| ShortcutBinding {
+                    action: *action,
+                    accelerator: Some(action.default_accelerator().to_string()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ShortcutBindings {
+    /// Fill in any actions missing from (e.g. persisted) bindings with their defaults, keeping
+    /// the bindings in the same order as ShortcutAction::ALL.
+    pub fn with_defaults(self) -> Self {
+        let defaults = ShortcutBindings::default();
+
+        ShortcutBindings {
+            bindings: ShortcutAction::ALL
+                .iter()
+                .map(|action| ShortcutBinding {
+                    action: *action,
+                    accelerator: self
+                        .bindings
+                        .iter()
+                        .chain(defaults.bindings.iter())
+                        .find(|binding| binding.action == *action)
+                        .and_then(|binding| binding.accelerator.clone()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn accelerator(&self, action: ShortcutAction) -> Option<&String> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.action == action)
+            .and_then(|binding| binding.accelerator.as_ref())
+    }
+
+    /// Bind an action to an accelerator (or unbind it, with None). Fails if another action is
+    /// already bound to the same accelerator.
+    pub fn set(
+        &mut self,
+        action: ShortcutAction,
+        accelerator: Option<String>,
+    ) -> Result<(), String> {
+        let accelerator = accelerator.filter(|accelerator| !accelerator.trim().is_empty());
+
+        if let Some(accelerator) = &accelerator {
+            let normalized = normalize_accelerator(accelerator);
+
+            let conflict = self.bindings.iter().find(|binding| {
+                binding.action != action
+                    && binding.accelerator.as_deref().map(normalize_accelerator).as_ref()
+                        == Some(&normalized)
+            });
+
+            if let Some(conflict) = conflict {
+                return Err(format!("{accelerator} is already used for {}", conflict.action));
+            }
+        }
+
+        match self.bindings.iter_mut().find(|binding| binding.action == action) {
+            Some(binding) => binding.accelerator = accelerator,
+            None => self.bindings.push(ShortcutBinding { action, accelerator }),
+        }
+
+        Ok(())
+    }
+}
+
+/// A comparable form of an accelerator: lowercase, with modifier aliases resolved and modifiers
+/// sorted. e.g. "Shift+CommandOrControl+P" and "cmdorctrl+shift+p" are the same.
+fn normalize_accelerator(accelerator: &str) -> String {
+    let mut parts: Vec<String> = accelerator
+        .split('+')
+        .map(|part| part.trim().to_lowercase())
+        .map(|part| match part.as_str() {
+            "commandorcontrol" => String::from("cmdorctrl"),
+            "command" | "cmd" | "meta" => String::from("super"),
+            "control" => String::from("ctrl"),
+            "option" => String::from("alt"),
+            _ => part,
+        })
+        .collect();
+
+    let key = parts.pop().unwrap_or_default();
+    parts.sort();
+    parts.push(key);
+
+    parts.join("+")
+}
+
+pub type ShortcutBindingsMutex = Arc<Mutex<ShortcutBindings>>;
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::shortcuts::{ShortcutAction, ShortcutBinding, ShortcutBindings};
+
+    #[test]
+    fn it_rejects_conflicting_bindings() {
+        let mut bindings = ShortcutBindings::default();
+
+        bindings.set(ShortcutAction::Mute, Some("Control+Shift+M".into())).unwrap();
+
+        let error = bindings.set(ShortcutAction::Next, Some("shift+ctrl+m".into())).unwrap_err();
+
+        assert_eq!(error, "shift+ctrl+m is already used for mute");
+        assert_eq!(
+            bindings.accelerator(ShortcutAction::Next).map(|accelerator| accelerator.as_str()),
+            Some("MediaTrackNext")
+        );
+
+        // Rebinding an action to its own accelerator isn't a conflict.
+        bindings.set(ShortcutAction::Mute, Some("CTRL+SHIFT+M".into())).unwrap();
+    }
+
+    #[test]
+    fn it_unbinds_and_fills_in_defaults() {
+        let mut bindings = ShortcutBindings::default();
+        bindings.set(ShortcutAction::PlayPause, Some(" ".into())).unwrap();
+
+        assert_eq!(bindings.accelerator(ShortcutAction::PlayPause), None);
+
+        let persisted = ShortcutBindings {
+            bindings: vec![ShortcutBinding { action: ShortcutAction::Mute, accelerator: None }],
+        };
+        let bindings = persisted.with_defaults();
+
+        assert_eq!(bindings.bindings.len(), ShortcutAction::ALL.len());
+        assert_eq!(bindings.accelerator(ShortcutAction::Mute), None);
+        assert_eq!(
+            bindings.accelerator(ShortcutAction::PlayPause).map(|accelerator| accelerator.as_str()),
+            Some("MediaPlayPause")
+        );
+    }
+}
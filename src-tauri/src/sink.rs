@@ -0,0 +1,229 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime, Wry};
+
+use crate::state::{
+    ActiveTrack, AppError, AppErrorCategory, AppState, Position, VibinState, WeeVibinMessage,
+};
+
+// ===============================================================================================
+// A StateSink receives the state and errors produced by the Vibin WebSocket connection. This keeps
+// the connection logic independent of where that information goes:
+//
+// TauriStateSink - Emits to the UI over Tauri message channels.
+// CompositeStateSink - Forwards to several sinks. Each feature which follows the connection (the
+//   tray, notifications, scrobbling, the listening history, the control API) is its own sink, and
+//   the application registers them alongside a TauriStateSink (see main.rs).
+// RecordingStateSink - Keeps everything in memory, for tests and headless use.
+//
+// Sinks only implement the methods for the information they're interested in.
+// ===============================================================================================
+
+pub trait StateSink: Send + Sync {
+    fn emit_app_state(&self, _app_state: &AppState) {}
+    fn emit_vibin_state(&self, _vibin_state: &VibinState) {}
+    fn emit_position(&self, _position: &Position) {}
+    fn emit_track_change(&self, _active_track: &ActiveTrack) {}
+    fn emit_error(&self, _error: &AppError) {}
+
+    fn emit_websocket_error(&self, error_message: &str) {
+        self.emit_error(&AppError {
+            category: AppErrorCategory::WebSocket,
+            message: error_message.into(),
+        });
+    }
+}
+
+pub type StateSinkRef = Arc<dyn StateSink>;
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct TauriStateSink<R: Runtime = Wry> {
+    app_handle: AppHandle<R>,
+}
+
+impl<R: Runtime> TauriStateSink<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
+        TauriStateSink { app_handle }
+    }
+}
+
+impl<R: Runtime> StateSink for TauriStateSink<R> {
+    fn emit_app_state(&self, app_state: &AppState) {
+        self.app_handle.emit_all(&WeeVibinMessage::AppState.to_string(), app_state).unwrap();
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.app_handle.emit_all(&WeeVibinMessage::VibinState.to_string(), vibin_state).unwrap();
+    }
+
+    fn emit_position(&self, position: &Position) {
+        self.app_handle.emit_all(&WeeVibinMessage::Position.to_string(), position).unwrap();
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        self.app_handle.emit_all(&WeeVibinMessage::TrackChange.to_string(), active_track).unwrap();
+    }
+
+    fn emit_error(&self, error: &AppError) {
+        self.app_handle.emit_all(&WeeVibinMessage::Error.to_string(), error).unwrap();
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Forwards everything to each of its sinks, in the order they were given.
+pub struct CompositeStateSink {
+    sinks: Vec<StateSinkRef>,
+}
+
+impl CompositeStateSink {
+    pub fn new(sinks: Vec<StateSinkRef>) -> Self {
+        CompositeStateSink { sinks }
+    }
+}
+
+impl StateSink for CompositeStateSink {
+    fn emit_app_state(&self, app_state: &AppState) {
+        self.sinks.iter().for_each(|sink| sink.emit_app_state(app_state));
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.sinks.iter().for_each(|sink| sink.emit_vibin_state(vibin_state));
+    }
+
+    fn emit_position(&self, position: &Position) {
+        self.sinks.iter().for_each(|sink| sink.emit_position(position));
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        self.sinks.iter().for_each(|sink| sink.emit_track_change(active_track));
+    }
+
+    fn emit_error(&self, error: &AppError) {
+        self.sinks.iter().for_each(|sink| sink.emit_error(error));
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum SinkEvent {
+    AppState(AppState),
+    VibinState(Box<VibinState>),
+    Position(Position),
+    TrackChange(ActiveTrack),
+    Error(AppError),
+}
+
+#[derive(Default)]
+pub struct RecordingStateSink {
+    events: Mutex<Vec<SinkEvent>>,
+}
+
+impl RecordingStateSink {
+    pub fn new() -> Self {
+        RecordingStateSink::default()
+    }
+
+    /// All events emitted so far, oldest first.
+    pub fn events(&self) -> Vec<SinkEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn app_states(&self) -> Vec<AppState> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                SinkEvent::AppState(app_state) => Some(app_state),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn positions(&self) -> Vec<Position> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                SinkEvent::Position(position) => Some(position),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn track_changes(&self) -> Vec<ActiveTrack> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                SinkEvent::TrackChange(active_track) => Some(active_track),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn errors(&self) -> Vec<AppError> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                SinkEvent::Error(error) => Some(error),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn push(&self, event: SinkEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl StateSink for RecordingStateSink {
+    fn emit_app_state(&self, app_state: &AppState) {
+        self.push(SinkEvent::AppState(app_state.clone()));
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.push(SinkEvent::VibinState(Box::new(vibin_state.clone())));
+    }
+
+    fn emit_position(&self, position: &Position) {
+        self.push(SinkEvent::Position(position.clone()));
+    }
+
+    fn emit_track_change(&self, active_track: &ActiveTrack) {
+        self.push(SinkEvent::TrackChange(active_track.clone()));
+    }
+
+    fn emit_error(&self, error: &AppError) {
+        self.push(SinkEvent::Error(error.clone()));
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::sink::{CompositeStateSink, RecordingStateSink, StateSink, StateSinkRef};
+    use crate::state::{AppState, Position};
+
+    #[test]
+    fn it_forwards_to_every_sink() {
+        let first = Arc::new(RecordingStateSink::new());
+        let second = Arc::new(RecordingStateSink::new());
+        let sinks: Vec<StateSinkRef> = vec![first.clone(), second.clone()];
+        let composite = CompositeStateSink::new(sinks);
+
+        composite.emit_app_state(&AppState::new());
+        composite.emit_position(&Position { position: 7 });
+        composite.emit_websocket_error("Connection lost");
+
+        for sink in [first, second] {
+            assert_eq!(sink.app_states().len(), 1);
+            assert_eq!(sink.positions()[0].position, 7);
+            assert_eq!(sink.errors()[0].message, "Connection lost");
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize, Serializer};
 use serde::ser::SerializeStruct;
@@ -10,6 +11,10 @@ use serde::ser::SerializeStruct;
 // AppState - The overall weevibin application state.
 // VibinState - Information about the current Vibin state (current track, transport details, etc).
 // Position - Current track position. This will be emitted frequently (likely once per second).
+// TrackChange - The new ActiveTrack, whenever a different track starts.
+// ServerProfiles - The saved Vibin server profiles, whenever they (or the active profile) change.
+// RoomEvent - Any of the above per-room messages, tagged with its room id (multi-room mode only).
+// Rooms - The multi-room mode rooms, whenever they (or the selected room) change.
 // Error - Any errors to be reported to the front-end.
 // ===============================================================================================
 
@@ -21,6 +26,10 @@ pub enum WeeVibinMessage {
     AppState,
     VibinState,
     Position,
+    TrackChange,
+    ServerProfiles,
+    RoomEvent,
+    Rooms,
     Error,
 }
 
@@ -75,25 +84,157 @@ impl Serialize for VibinConnectionState {
     }
 }
 
+/// Details of the next scheduled WebSocket reconnect attempt. `retry_at` is milliseconds since
+/// the Unix epoch, which allows the UI to display a countdown.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconnectStatus {
+    pub attempt: u32,
+    pub delay_secs: f64,
+    pub retry_at: u64,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct AppState {
     pub vibin_connection: VibinConnectionState,
+    pub reconnect: Option<ReconnectStatus>,
 }
 
 impl AppState {
     pub fn new() -> AppState {
         AppState {
             vibin_connection: VibinConnectionState::Disconnected(None),
+            reconnect: None,
         }
     }
 
     pub fn set_disconnected(&mut self, message: Option<String>) {
         self.vibin_connection = VibinConnectionState::Disconnected(message);
     }
+
+    pub fn set_reconnecting(&mut self, attempt: u32, delay: Duration) {
+        let retry_at = SystemTime::now()
+            .checked_add(delay)
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or(0);
+
+        self.reconnect = Some(ReconnectStatus {
+            attempt,
+            delay_secs: delay.as_secs_f64(),
+            retry_at,
+        });
+    }
 }
 
 pub type AppStateMutex = Arc<Mutex<AppState>>;
 
+// -----------------------------------------------------------------------------------------------
+// Errors
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum AppErrorCategory {
+    WebSocket,
+    VibinApi,
+    MessageParse,
+    Shortcut,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppError {
+    pub category: AppErrorCategory,
+    pub message: String,
+}
+
+// -----------------------------------------------------------------------------------------------
+// Vibin enums
+//
+// Vibin sends these as well-defined strings. Each is mapped to an enum variant, with anything
+// unrecognized (e.g. a value added in a newer Vibin) kept in an Unknown variant rather than
+// failing deserialization. They serialize back to the original strings for the UI.
+
+macro_rules! vibin_string_enum {
+    ($name:ident { $($variant:ident => $value:literal),+ $(,)? }) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum $name {
+            $($variant,)+
+            Unknown(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $value,)+
+                    $name::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                match value.as_str() {
+                    $($value => $name::$variant,)+
+                    _ => $name::Unknown(value),
+                }
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                match value {
+                    $name::Unknown(value) => value,
+                    known => known.as_str().into(),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+vibin_string_enum!(PowerState {
+    On => "on",
+    Off => "off",
+});
+
+vibin_string_enum!(PlayState {
+    Buffering => "buffering",
+    Connecting => "connecting",
+    NoSignal => "no_signal",
+    NotReady => "not_ready",
+    Pause => "pause",
+    Play => "play",
+    Ready => "ready",
+    Stop => "stop",
+});
+
+vibin_string_enum!(RepeatState {
+    Off => "off",
+    All => "all",
+});
+
+vibin_string_enum!(ShuffleState {
+    Off => "off",
+    All => "all",
+});
+
+vibin_string_enum!(SourceClass {
+    DigitalCoax => "digital.coax",
+    DigitalToslink => "digital.toslink",
+    DigitalUsb => "digital.usb",
+    StreamMedia => "stream.media",
+    StreamRadio => "stream.radio",
+    StreamServiceAirplay => "stream.service.airplay",
+    StreamServiceCast => "stream.service.cast",
+    StreamServiceRoon => "stream.service.roon",
+    StreamServiceSpotify => "stream.service.spotify",
+    StreamServiceTidal => "stream.service.tidal",
+});
+
 // -----------------------------------------------------------------------------------------------
 // VibinState
 //
@@ -102,7 +243,7 @@ pub type AppStateMutex = Arc<Mutex<AppState>>;
 // collect all the disparate vibin information into a single struct, which is then sent to the
 // UI code (over a message channel) for display.
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamerDisplay {
     pub line1: Option<String>,
     pub line2: Option<String>,
@@ -112,12 +253,12 @@ pub struct StreamerDisplay {
     pub art_url: Option<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Source {
     pub id: Option<String>,
     pub name: Option<String>,
     pub default_name: Option<String>,
-    pub class: Option<String>,
+    pub class: Option<SourceClass>,
     pub nameable: Option<bool>,
     pub ui_selectable: Option<bool>,
     pub description: Option<String>,
@@ -125,53 +266,206 @@ pub struct Source {
     pub preferred_order: Option<isize>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl Source {
+    pub fn is_airplay(&self) -> bool {
+        self.class == Some(SourceClass::StreamServiceAirplay)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamerSources {
     pub active: Source,
     pub available: Vec<Source>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Amplifier {
     pub mute: Option<String>,
     pub volume: Option<f32>,
 }
 
-// TODO: Consider adding proper types for play_state, active_controls, etc. Keeping them as
-//  strings is arguably too loose as they are actually well-defined strings (enums).
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransportState {
-    pub play_state: Option<String>,
+    pub play_state: Option<PlayState>,
     pub active_controls: Vec<String>,
-    pub repeat: Option<String>,
-    pub shuffle: Option<String>,
+    pub repeat: Option<RepeatState>,
+    pub shuffle: Option<ShuffleState>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl TransportState {
+    /// Whether the streamer currently allows the given transport control (e.g. "next").
+    pub fn is_active_control(&self, control: &str) -> bool {
+        self.active_controls.iter().any(|active_control| active_control == control)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.play_state == Some(PlayState::Play)
+    }
+
+    pub fn is_buffering(&self) -> bool {
+        self.play_state == Some(PlayState::Buffering)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActiveTrack {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
     pub art_url: Option<String>,
     pub duration: Option<isize>,
+    pub album_media_id: Option<String>,
+    pub track_media_id: Option<String>,
+    pub stream_url: Option<String>,
+}
+
+impl ActiveTrack {
+    /// Whether `other` is the same track as this one. Titles (etc) can repeat across different
+    /// tracks, so the media ids are compared when both tracks have one. Tracks without media ids
+    /// (e.g. AirPlay, internet radio) are compared on their title, artist and album.
+    pub fn is_same_track(&self, other: &ActiveTrack) -> bool {
+        match (&self.track_media_id, &other.track_media_id) {
+            (Some(track_media_id), Some(other_track_media_id)) => {
+                track_media_id == other_track_media_id
+            }
+            _ => {
+                self.title == other.title && self.artist == other.artist && self.album == other.album
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioFormat {
+    pub sample_format: Option<String>,
+    pub mqa: Option<String>,
+    pub codec: Option<String>,
+    pub lossless: Option<bool>,
+    pub sample_rate: Option<isize>,
+    pub bit_depth: Option<isize>,
+    pub encoding: Option<String>,
+    /// Human-readable summary of the above, e.g. "FLAC 24/96 lossless, MQA studio".
+    pub summary: Option<String>,
+}
+
+impl AudioFormat {
+    /// Populate `summary` from the other format details.
+    pub fn with_summary(mut self) -> Self {
+        self.summary = self.summarize();
+        self
+    }
+
+    fn summarize(&self) -> Option<String> {
+        let mut parts: Vec<String> = Vec::new();
+
+        let codec = self.codec.as_ref().or(self.encoding.as_ref()).filter(|codec| !codec.is_empty());
+
+        if let Some(codec) = codec {
+            parts.push(codec.to_uppercase());
+        }
+
+        let sample_rate_khz = self.sample_rate.filter(|rate| *rate > 0).map(|rate| {
+            let khz = format!("{:.1}", rate as f64 / 1000.0);
+            khz.strip_suffix(".0").map(String::from).unwrap_or(khz)
+        });
+
+        match (self.bit_depth.filter(|depth| *depth > 0), sample_rate_khz) {
+            (Some(bit_depth), Some(khz)) => parts.push(format!("{bit_depth}/{khz}")),
+            (None, Some(khz)) => parts.push(format!("{khz}kHz")),
+            (Some(bit_depth), None) => parts.push(format!("{bit_depth}-bit")),
+            (None, None) => {}
+        }
+
+        if self.lossless == Some(true) {
+            parts.push(String::from("lossless"));
+        }
+
+        if parts.is_empty() {
+            return None;
+        }
+
+        let mut summary = parts.join(" ");
+
+        match self.mqa.as_deref() {
+            None | Some("") | Some("none") => {}
+            Some(mqa) => summary.push_str(&format!(", MQA {mqa}")),
+        }
+
+        Some(summary)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueEntry {
+    /// Vibin's id for the entry, which is what the queue commands (play, delete, move) refer to.
+    pub id: isize,
+    /// Zero-based position of the entry in the queue.
+    pub position: usize,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub art_url: Option<String>,
+    pub duration: Option<isize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Queue {
+    pub entries: Vec<QueueEntry>,
+    /// Position of the currently-playing entry, if any.
+    pub current_index: Option<usize>,
+    /// Total duration (in seconds) of all entries with a known duration.
+    pub total_duration: isize,
 }
 
-#[derive(Clone, Serialize)]
+impl Queue {
+    pub fn new(entries: Vec<QueueEntry>, current_index: Option<usize>) -> Self {
+        let total_duration = entries.iter().filter_map(|entry| entry.duration).sum();
+
+        Queue {
+            entries,
+            current_index,
+            total_duration,
+        }
+    }
+
+    pub fn current_entry(&self) -> Option<&QueueEntry> {
+        self.current_index
+            .and_then(|index| self.entries.iter().find(|entry| entry.position == index))
+    }
+
+    /// The entries after the currently-playing entry (or all entries if nothing is playing).
+    pub fn upcoming(&self) -> impl Iterator<Item = &QueueEntry> {
+        let current_index = self.current_index;
+
+        self.entries
+            .iter()
+            .filter(move |entry| match current_index {
+                Some(index) => entry.position > index,
+                None => true,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct VibinState {
-    pub power: Option<String>,
-    pub streamer_power: Option<String>,
+    pub power: Option<PowerState>,
+    pub streamer_power: Option<PowerState>,
     pub amplifier: Option<Amplifier>,
     pub display: StreamerDisplay,
     pub transport: Option<TransportState>,
     pub source: Option<Source>,
+    /// The sources which can be selected by the user, in the streamer's preferred order.
+    pub sources: Vec<Source>,
     pub active_track: Option<ActiveTrack>,
+    pub audio_format: Option<AudioFormat>,
+    pub queue: Option<Queue>,
 }
 
 impl VibinState {
     pub fn new() -> VibinState {
         VibinState {
-            power: Some("off".into()),
-            streamer_power: Some("off".into()),
+            power: Some(PowerState::Off),
+            streamer_power: Some(PowerState::Off),
             amplifier: None,
             display: StreamerDisplay {
                 line1: None,
@@ -183,9 +477,34 @@ impl VibinState {
             },
             transport: None,
             source: None,
+            sources: Vec::new(),
             active_track: None,
+            audio_format: None,
+            queue: None,
         }
     }
+
+    /// Whether the system's overall power state (the top-level power in Vibin's System message)
+    /// is on. Vibin doesn't report the amplifier's power, so it isn't considered.
+    pub fn is_system_power_on(&self) -> bool {
+        self.power == Some(PowerState::On)
+    }
+
+    pub fn is_streamer_power_on(&self) -> bool {
+        self.streamer_power == Some(PowerState::On)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.transport.as_ref().is_some_and(|transport| transport.is_playing())
+    }
+
+    pub fn is_airplay(&self) -> bool {
+        self.source.as_ref().is_some_and(|source| source.is_airplay())
+    }
+
+    pub fn source_by_id(&self, source_id: &str) -> Option<&Source> {
+        self.sources.iter().find(|source| source.id.as_deref() == Some(source_id))
+    }
 }
 
 pub type VibinStateMutex = Arc<Mutex<VibinState>>;
@@ -193,8 +512,136 @@ pub type VibinStateMutex = Arc<Mutex<VibinState>>;
 // -----------------------------------------------------------------------------------------------
 // Position
 
-#[derive(Clone, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Position {
     pub position: isize,
 }
 
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{
+        ActiveTrack, AudioFormat, PlayState, PowerState, Queue, QueueEntry, SourceClass,
+        TransportState,
+    };
+
+    fn active_track(title: &str, track_media_id: Option<&str>) -> ActiveTrack {
+        ActiveTrack {
+            title: Some(title.into()),
+            artist: Some("Artist".into()),
+            album: Some("Album".into()),
+            art_url: None,
+            duration: None,
+            album_media_id: None,
+            track_media_id: track_media_id.map(String::from),
+            stream_url: None,
+        }
+    }
+
+    fn audio_format(codec: &str, sample_rate: isize, bit_depth: isize, lossless: bool) -> AudioFormat {
+        AudioFormat {
+            sample_format: None,
+            mqa: None,
+            codec: Some(codec.into()),
+            lossless: Some(lossless),
+            sample_rate: Some(sample_rate),
+            bit_depth: Some(bit_depth),
+            encoding: None,
+            summary: None,
+        }
+    }
+
+    fn queue_entry(id: isize, position: usize, duration: Option<isize>) -> QueueEntry {
+        QueueEntry {
+            id,
+            position,
+            title: Some(format!("Track {position}")),
+            artist: None,
+            album: None,
+            art_url: None,
+            duration,
+        }
+    }
+
+    #[test]
+    fn it_deserializes_known_values() {
+        let transport: TransportState = serde_json::from_str(
+            r#"{"play_state": "not_ready", "active_controls": [], "repeat": "all", "shuffle": "off"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(transport.play_state, Some(PlayState::NotReady));
+        assert_eq!(
+            serde_json::from_str::<SourceClass>(r#""stream.service.airplay""#).unwrap(),
+            SourceClass::StreamServiceAirplay
+        );
+    }
+
+    #[test]
+    fn it_keeps_unknown_values() {
+        let power: PowerState = serde_json::from_str(r#""standby""#).unwrap();
+
+        assert_eq!(power, PowerState::Unknown("standby".into()));
+        assert_eq!(serde_json::to_string(&power).unwrap(), r#""standby""#);
+    }
+
+    #[test]
+    fn it_serializes_to_vibin_strings() {
+        assert_eq!(serde_json::to_string(&PlayState::NoSignal).unwrap(), r#""no_signal""#);
+        assert_eq!(SourceClass::StreamMedia.to_string(), "stream.media");
+    }
+
+    #[test]
+    fn it_summarizes_the_queue() {
+        let queue = Queue::new(
+            vec![
+                queue_entry(10, 0, Some(100)),
+                queue_entry(11, 1, None),
+                queue_entry(12, 2, Some(200)),
+            ],
+            Some(1),
+        );
+
+        assert_eq!(queue.total_duration, 300);
+        assert_eq!(queue.current_entry().map(|entry| entry.id), Some(11));
+        assert_eq!(queue.upcoming().map(|entry| entry.id).collect::<Vec<_>>(), vec![12]);
+    }
+
+    #[test]
+    fn it_identifies_tracks_by_media_id() {
+        assert!(active_track("Intro", Some("1")).is_same_track(&active_track("Intro", Some("1"))));
+        assert!(!active_track("Intro", Some("1")).is_same_track(&active_track("Intro", Some("2"))));
+        assert!(active_track("Intro", None).is_same_track(&active_track("Intro", Some("2"))));
+        assert!(!active_track("Intro", None).is_same_track(&active_track("Outro", None)));
+    }
+
+    #[test]
+    fn it_summarizes_audio_formats() {
+        let flac = audio_format("flac", 96000, 24, true).with_summary();
+        let mp3 = audio_format("MP3", 44100, 0, false).with_summary();
+        let mqa = AudioFormat {
+            mqa: Some("studio".into()),
+            ..audio_format("FLAC", 96000, 24, true)
+        }
+        .with_summary();
+
+        assert_eq!(flac.summary.as_deref(), Some("FLAC 24/96 lossless"));
+        assert_eq!(mp3.summary.as_deref(), Some("MP3 44.1kHz"));
+        assert_eq!(mqa.summary.as_deref(), Some("FLAC 24/96 lossless, MQA studio"));
+    }
+
+    #[test]
+    fn it_has_no_summary_for_unknown_formats() {
+        let unknown = AudioFormat {
+            codec: None,
+            sample_rate: None,
+            bit_depth: None,
+            lossless: None,
+            ..audio_format("", 0, 0, false)
+        }
+        .with_summary();
+
+        assert_eq!(unknown.summary, None);
+    }
+}
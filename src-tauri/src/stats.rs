@@ -0,0 +1,379 @@
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::history::ListeningHistory;
+
+// ===============================================================================================
+// Listening statistics, aggregated from the listening history (see history.rs) over a period
+// ending now: top artists, albums, and tracks; listening time per day and per week; and
+// breakdowns by source class and by audio quality.
+//
+// The top lists count listens (plays which weren't skipped), while listening time includes
+// everything that was played. Days and weeks (starting on Monday) are in local time, and a play
+// counts towards the day it started on.
+// ===============================================================================================
+
+const DEFAULT_TOP_LIMIT: usize = 10;
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+    Year,
+    AllTime,
+}
+
+impl StatsPeriod {
+    /// When the period starts (in seconds since the epoch), for a period ending at `now`.
+    fn start(&self, now: i64) -> i64 {
+        let days = match self {
+            StatsPeriod::Day => 1,
+            StatsPeriod::Week => 7,
+            StatsPeriod::Month => 30,
+            StatsPeriod::Year => 365,
+            StatsPeriod::AllTime => return 0,
+        };
+
+        now - days * SECS_PER_DAY
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopEntry {
+    pub artist: Option<String>,
+    /// None for top artists.
+    pub album: Option<String>,
+    /// None for top artists and albums.
+    pub title: Option<String>,
+    pub listens: usize,
+    pub listened_secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListeningTime {
+    /// The local date of the day (or of the Monday starting the week), e.g. "2023-11-06".
+    pub date: String,
+    pub listened_secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Breakdown {
+    /// The source class (e.g. "stream.media") or audio quality (see AUDIO_QUALITY). "unknown"
+    /// when it wasn't known.
+    pub name: String,
+    pub plays: usize,
+    pub listened_secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListeningStats {
+    pub period: StatsPeriod,
+    /// The period's start and end, in seconds since the epoch.
+    pub from: i64,
+    pub to: i64,
+    pub plays: usize,
+    pub skips: usize,
+    pub listened_secs: i64,
+    pub top_artists: Vec<TopEntry>,
+    pub top_albums: Vec<TopEntry>,
+    pub top_tracks: Vec<TopEntry>,
+    pub daily: Vec<ListeningTime>,
+    pub weekly: Vec<ListeningTime>,
+    pub by_source_class: Vec<Breakdown>,
+    pub by_audio_quality: Vec<Breakdown>,
+}
+
+// The local date of a play's day, and of the Monday starting its week.
+const PLAY_DAY: &str = "date(started_at, 'unixepoch', 'localtime')";
+const PLAY_WEEK: &str = "date(started_at, 'unixepoch', 'localtime', 'weekday 0', '-6 days')";
+
+// "hi_res" - Lossless, and better than CD quality (more than 16 bits or 48kHz).
+// "cd" - Lossless, at CD quality (up to 16 bits and 48kHz).
+// "lossy" - e.g. MP3 or AAC.
+const AUDIO_QUALITY: &str = "CASE
+    WHEN lossless = 0 THEN 'lossy'
+    WHEN lossless = 1 AND (bit_depth > 16 OR sample_rate > 48000) THEN 'hi_res'
+    WHEN lossless = 1 AND bit_depth IS NOT NULL AND sample_rate IS NOT NULL THEN 'cd'
+    ELSE 'unknown'
+END";
+
+/// Statistics for the period ending at `now` (seconds since the epoch). `top_limit` is the
+/// length of each top list.
+pub fn listening_stats(
+    history: &ListeningHistory,
+    period: StatsPeriod,
+    top_limit: Option<usize>,
+    now: i64,
+) -> rusqlite::Result<ListeningStats> {
+    let from = period.start(now);
+    let top_limit = top_limit.unwrap_or(DEFAULT_TOP_LIMIT);
+
+    history.query(|connection| {
+        let (plays, skips, listened_secs) = connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(skipped), 0), COALESCE(SUM(listened_secs), 0)
+            FROM plays WHERE started_at >= ?1 AND started_at <= ?2",
+            params![from, now],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(ListeningStats {
+            period,
+            from,
+            to: now,
+            plays,
+            skips,
+            listened_secs,
+            top_artists: top(connection, Top::Artists, from, now, top_limit)?,
+            top_albums: top(connection, Top::Albums, from, now, top_limit)?,
+            top_tracks: top(connection, Top::Tracks, from, now, top_limit)?,
+            daily: listening_time(connection, PLAY_DAY, from, now)?,
+            weekly: listening_time(connection, PLAY_WEEK, from, now)?,
+            by_source_class: breakdown(connection, "COALESCE(source_class, 'unknown')", from, now)?,
+            by_audio_quality: breakdown(connection, AUDIO_QUALITY, from, now)?,
+        })
+    })
+}
+
+enum Top {
+    Artists,
+    Albums,
+    Tracks,
+}
+
+impl Top {
+    /// The (artist, album, title) columns describing each entry.
+    fn columns(&self) -> &'static str {
+        match self {
+            Top::Artists => "artist, NULL, NULL",
+            Top::Albums => "artist, album, NULL",
+            Top::Tracks => "artist, album, title",
+        }
+    }
+
+    /// The columns identifying each entry.
+    fn group(&self) -> &'static str {
+        match self {
+            Top::Artists => "artist",
+            Top::Albums => "artist, album",
+            Top::Tracks => "artist, title",
+        }
+    }
+}
+
+/// The most listened to artists, albums, or tracks.
+fn top(
+    connection: &Connection,
+    top: Top,
+    from: i64,
+    to: i64,
+    limit: usize,
+) -> rusqlite::Result<Vec<TopEntry>> {
+    let (columns, group) = (top.columns(), top.group());
+
+    // Only include entries which have all the identifying details.
+    let is_grouped = group
+        .split(", ")
+        .map(|column| format!("{column} IS NOT NULL"))
+        .collect::<Vec<String>>()
+        .join(" AND ");
+
+    connection
+        .prepare(&format!(
+            "SELECT {columns}, COUNT(*) AS listens, SUM(listened_secs) AS listened_secs
+            FROM plays
+            WHERE started_at >= ?1 AND started_at <= ?2 AND skipped = 0 AND {is_grouped}
+            GROUP BY {group}
+            ORDER BY listens DESC, listened_secs DESC
+            LIMIT ?3"
+        ))?
+        .query_map(params![from, to, limit], |row: &Row| {
+            Ok(TopEntry {
+                artist: row.get(0)?,
+                album: row.get(1)?,
+                title: row.get(2)?,
+                listens: row.get(3)?,
+                listened_secs: row.get(4)?,
+            })
+        })?
+        .collect()
+}
+
+/// Listening time per `date`, oldest first.
+fn listening_time(
+    connection: &Connection,
+    date: &str,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<ListeningTime>> {
+    connection
+        .prepare(&format!(
+            "SELECT {date} AS date, SUM(listened_secs)
+            FROM plays
+            WHERE started_at >= ?1 AND started_at <= ?2
+            GROUP BY date
+            ORDER BY date"
+        ))?
+        .query_map(params![from, to], |row| {
+            Ok(ListeningTime { date: row.get(0)?, listened_secs: row.get(1)? })
+        })?
+        .collect()
+}
+
+/// Plays and listening time per `name`, most listened first.
+fn breakdown(
+    connection: &Connection,
+    name: &str,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<Breakdown>> {
+    connection
+        .prepare(&format!(
+            "SELECT {name} AS name, COUNT(*), SUM(listened_secs) AS listened_secs
+            FROM plays
+            WHERE started_at >= ?1 AND started_at <= ?2
+            GROUP BY name
+            ORDER BY listened_secs DESC"
+        ))?
+        .query_map(params![from, to], |row| {
+            Ok(Breakdown { name: row.get(0)?, plays: row.get(1)?, listened_secs: row.get(2)? })
+        })?
+        .collect()
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::history::ListeningHistory;
+    use crate::playback::Play;
+    use crate::state::{ActiveTrack, AudioFormat, SourceClass};
+    use crate::stats::{listening_stats, Breakdown, StatsPeriod};
+
+    const NOW: i64 = 1_700_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+
+    struct Record<'a> {
+        artist: &'a str,
+        album: &'a str,
+        title: &'a str,
+        days_ago: i64,
+        played_secs: isize,
+        source_class: SourceClass,
+        format: Option<(bool, isize, isize)>,
+    }
+
+    fn record(history: &ListeningHistory, record: Record) {
+        history
+            .record(&Play {
+                track: ActiveTrack {
+                    title: Some(record.title.into()),
+                    artist: Some(record.artist.into()),
+                    album: Some(record.album.into()),
+                    art_url: None,
+                    duration: Some(200),
+                    album_media_id: None,
+                    track_media_id: None,
+                    stream_url: None,
+                },
+                source_class: Some(record.source_class),
+                audio_format: record.format.map(|(lossless, sample_rate, bit_depth)| AudioFormat {
+                    sample_format: None,
+                    mqa: None,
+                    codec: None,
+                    lossless: Some(lossless),
+                    sample_rate: Some(sample_rate),
+                    bit_depth: Some(bit_depth),
+                    encoding: None,
+                    summary: None,
+                }),
+                started_at: NOW - record.days_ago * DAY,
+                played_secs: record.played_secs,
+            })
+            .unwrap();
+    }
+
+    fn history() -> ListeningHistory {
+        let history = ListeningHistory::open_in_memory().unwrap();
+        let media = SourceClass::StreamMedia;
+        let radio = SourceClass::StreamRadio;
+
+        let records = [
+            ("Nils Frahm", "Spaces", "Says", 0, 200, media.clone(), Some((true, 96000, 24))),
+            ("Nils Frahm", "Spaces", "Says", 0, 150, media.clone(), Some((true, 44100, 16))),
+            ("Nils Frahm", "Felt", "Familiar", 2, 100, media.clone(), Some((true, 44100, 16))),
+            ("Hania Rani", "Home", "Leaving", 2, 10, media.clone(), None),
+            ("Hania Rani", "Home", "Glass", 3, 180, radio, Some((false, 44100, 16))),
+            ("Hania Rani", "Home", "Eden", 60, 200, media, Some((true, 44100, 16))),
+        ];
+
+        for (artist, album, title, days_ago, played_secs, source_class, format) in records {
+            record(
+                &history,
+                Record { artist, album, title, days_ago, played_secs, source_class, format },
+            );
+        }
+
+        history
+    }
+
+    #[test]
+    fn it_totals_plays_within_the_period() {
+        let history = history();
+
+        let week = listening_stats(&history, StatsPeriod::Week, None, NOW).unwrap();
+        assert_eq!(week.from, NOW - 7 * DAY);
+        assert_eq!(week.plays, 5);
+        assert_eq!(week.skips, 1);
+        assert_eq!(week.listened_secs, 640);
+        assert_eq!(week.daily.len(), 3);
+        assert_eq!(week.daily.iter().map(|day| day.listened_secs).sum::<i64>(), 640);
+
+        let all_time = listening_stats(&history, StatsPeriod::AllTime, None, NOW).unwrap();
+        assert_eq!(all_time.plays, 6);
+        assert_eq!(all_time.weekly.iter().map(|week| week.listened_secs).sum::<i64>(), 840);
+    }
+
+    #[test]
+    fn it_ranks_top_listens() {
+        let stats = listening_stats(&history(), StatsPeriod::Week, Some(1), NOW).unwrap();
+
+        assert_eq!(stats.top_artists.len(), 1);
+        assert_eq!(stats.top_artists[0].artist.as_deref(), Some("Nils Frahm"));
+        assert_eq!(stats.top_artists[0].listens, 3);
+        assert_eq!(stats.top_albums[0].album.as_deref(), Some("Spaces"));
+        assert_eq!(stats.top_tracks[0].title.as_deref(), Some("Says"));
+        assert_eq!(stats.top_tracks[0].listened_secs, 350);
+
+        // "Leaving" was skipped, so Hania Rani has one listen this week.
+        let stats = listening_stats(&history(), StatsPeriod::Week, None, NOW).unwrap();
+        assert_eq!(stats.top_artists[1].listens, 1);
+    }
+
+    #[test]
+    fn it_breaks_down_by_source_and_quality() {
+        let stats = listening_stats(&history(), StatsPeriod::Week, None, NOW).unwrap();
+
+        let breakdown = |name: &str, plays: usize, listened_secs: i64| Breakdown {
+            name: name.into(),
+            plays,
+            listened_secs,
+        };
+
+        assert_eq!(
+            stats.by_source_class,
+            vec![breakdown("stream.media", 4, 460), breakdown("stream.radio", 1, 180)]
+        );
+        assert_eq!(
+            stats.by_audio_quality,
+            vec![
+                breakdown("cd", 2, 250),
+                breakdown("hi_res", 1, 200),
+                breakdown("lossy", 1, 180),
+                breakdown("unknown", 1, 10),
+            ]
+        );
+    }
+}
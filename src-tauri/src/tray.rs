@@ -0,0 +1,732 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use tauri::{
+    AppHandle, CustomMenuItem, Icon, Runtime, SystemTrayMenu, SystemTrayMenuItem,
+    SystemTraySubmenu, Wry,
+};
+use tokio::time::sleep;
+
+use crate::profiles::ServerProfiles;
+use crate::sink::StateSink;
+use crate::state::{AppState, PlayState, TransportState, VibinConnectionState, VibinState};
+use crate::vibin_api::TransportAction;
+
+// ===============================================================================================
+// The system tray menu. The menu reflects parts of the VibinState (such as the current track, the
+// available transport controls, and the available sources) and the server profiles, so it's
+// rebuilt whenever those parts change.
+//
+// Menu item ids are used to identify clicks (see main.rs's on_system_tray_event). Items which
+// represent a value (like a source) have ids of the form "<prefix><value>".
+//
+// The tray icon, tooltip, and title (on macOS) summarize the connection and the current track.
+// These are updated at most once per TRAY_STATUS_MIN_INTERVAL.
+// ===============================================================================================
+
+pub const QUIT_ID: &str = "quit";
+pub const NOW_PLAYING_ID: &str = "now_playing";
+pub const PLAY_PAUSE_ID: &str = "play_pause";
+pub const NEXT_ID: &str = "next";
+pub const PREVIOUS_ID: &str = "previous";
+pub const MUTE_ID: &str = "mute";
+pub const POWER_ID: &str = "power";
+pub const SOURCE_ID_PREFIX: &str = "source:";
+pub const PROFILE_ID_PREFIX: &str = "profile:";
+
+const TRAY_STATUS_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+// Matches the UI's IS_BUFFERING_DELAY (see state.ts).
+const BUFFERING_ICON_DELAY: Duration = Duration::from_secs(2);
+const TRAY_TITLE_MAX_CHARS: usize = 24;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraySource {
+    pub id: String,
+    pub name: String,
+}
+
+/// The transport action for a play/pause control, given what the streamer currently allows. None
+/// if the streamer can't currently be played or paused.
+pub fn play_pause_action(transport: &TransportState) -> Option<TransportAction> {
+    let can = |control: &str| transport.is_active_control(control);
+
+    match transport.is_playing() {
+        true if can("pause") => Some(TransportAction::Pause),
+        true if can("toggle_playback") => Some(TransportAction::TogglePlayback),
+        true if can("stop") => Some(TransportAction::Stop),
+        false if can("play") => Some(TransportAction::Play),
+        false if can("toggle_playback") => Some(TransportAction::TogglePlayback),
+        _ => None,
+    }
+}
+
+/// Everything the tray menu displays. The menu is only rebuilt when this changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrayMenuState {
+    pub now_playing: Option<String>,
+    pub is_playing: bool,
+    pub play_pause_action: Option<TransportAction>,
+    pub can_next: bool,
+    pub can_previous: bool,
+    /// None when there's no amplifier to mute.
+    pub is_muted: Option<bool>,
+    pub is_power_on: bool,
+    pub sources: Vec<TraySource>,
+    pub active_source_id: Option<String>,
+    pub profile_names: Vec<String>,
+    pub active_profile: Option<String>,
+}
+
+impl TrayMenuState {
+    pub fn set_vibin_state(&mut self, vibin_state: &VibinState) {
+        self.now_playing = vibin_state.active_track.as_ref().and_then(|track| {
+            match (track.title.as_deref(), track.artist.as_deref()) {
+                (Some(title), Some(artist)) => Some(format!("{title} — {artist}")),
+                (Some(title), None) => Some(title.to_string()),
+                _ => None,
+            }
+        });
+
+        let transport = vibin_state.transport.as_ref();
+
+        self.is_playing = vibin_state.is_playing();
+        self.play_pause_action = transport.and_then(play_pause_action);
+        self.can_next = transport.is_some_and(|transport| transport.is_active_control("next"));
+        self.can_previous =
+            transport.is_some_and(|transport| transport.is_active_control("previous"));
+        self.is_muted = vibin_state
+            .amplifier
+            .as_ref()
+            .map(|amplifier| amplifier.mute.as_deref() == Some("on"));
+        self.is_power_on = vibin_state.is_system_power_on();
+
+        self.sources = vibin_state
+            .sources
+            .iter()
+            .filter_map(|source| {
+                let id = source.id.clone()?;
+                let name = source.name.clone().or(source.default_name.clone())?;
+
+                Some(TraySource { id, name })
+            })
+            .collect();
+        self.active_source_id = vibin_state.source.as_ref().and_then(|source| source.id.clone());
+    }
+
+    pub fn set_profiles(&mut self, profiles: &ServerProfiles) {
+        self.profile_names = profiles.profiles.iter().map(|profile| profile.name.clone()).collect();
+        self.active_profile = profiles.active.clone();
+    }
+}
+
+/// Build a radio-style submenu, where only the item matching `selected_value` is selected.
+fn radio_submenu(
+    title: &str,
+    id_prefix: &str,
+    items: &[(String, String)],
+    selected_value: Option<&String>,
+) -> SystemTraySubmenu {
+    let menu = items.iter().fold(SystemTrayMenu::new(), |menu, (value, title)| {
+        let item = CustomMenuItem::new(format!("{id_prefix}{value}"), title);
+
+        menu.add_item(match selected_value == Some(value) {
+            true => item.selected(),
+            false => item,
+        })
+    });
+
+    SystemTraySubmenu::new(title, menu)
+}
+
+/// A menu item which is disabled when `enabled` is false.
+fn menu_item(id: &str, title: &str, enabled: bool) -> CustomMenuItem {
+    let item = CustomMenuItem::new(id.to_string(), title);
+
+    match enabled {
+        true => item,
+        false => item.disabled(),
+    }
+}
+
+/// Build the tray menu. Items and submenus are only included when they have something to show.
+pub fn build_tray_menu(menu_state: &TrayMenuState) -> SystemTrayMenu {
+    let mut menu = SystemTrayMenu::new();
+
+    if let Some(now_playing) = &menu_state.now_playing {
+        menu = menu.add_item(menu_item(NOW_PLAYING_ID, now_playing, false));
+    }
+
+    let play_pause_title = match menu_state.is_playing {
+        true => "Pause",
+        false => "Play",
+    };
+
+    menu = menu
+        .add_item(menu_item(
+            PLAY_PAUSE_ID,
+            play_pause_title,
+            menu_state.play_pause_action.is_some(),
+        ))
+        .add_item(menu_item(PREVIOUS_ID, "Previous", menu_state.can_previous))
+        .add_item(menu_item(NEXT_ID, "Next", menu_state.can_next))
+        .add_native_item(SystemTrayMenuItem::Separator);
+
+    if let Some(is_muted) = menu_state.is_muted {
+        menu = menu.add_item(menu_item(MUTE_ID, if is_muted { "Unmute" } else { "Mute" }, true));
+    }
+
+    let power_title = match menu_state.is_power_on {
+        true => "Power Off",
+        false => "Power On",
+    };
+
+    menu = menu
+        .add_item(menu_item(POWER_ID, power_title, true))
+        .add_native_item(SystemTrayMenuItem::Separator);
+
+    if !menu_state.profile_names.is_empty() {
+        let profiles: Vec<(String, String)> =
+            menu_state.profile_names.iter().map(|name| (name.clone(), name.clone())).collect();
+
+        menu = menu.add_submenu(radio_submenu(
+            "Vibin Server",
+            PROFILE_ID_PREFIX,
+            &profiles,
+            menu_state.active_profile.as_ref(),
+        ));
+    }
+
+    if !menu_state.sources.is_empty() {
+        let sources: Vec<(String, String)> = menu_state
+            .sources
+            .iter()
+            .map(|source| (source.id.clone(), source.name.clone()))
+            .collect();
+
+        menu = menu.add_submenu(radio_submenu(
+            "Source",
+            SOURCE_ID_PREFIX,
+            &sources,
+            menu_state.active_source_id.as_ref(),
+        ));
+    }
+
+    if !(menu_state.profile_names.is_empty() && menu_state.sources.is_empty()) {
+        menu = menu.add_native_item(SystemTrayMenuItem::Separator);
+    }
+
+    menu.add_item(CustomMenuItem::new(QUIT_ID.to_string(), "Quit WeeVibin"))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Maintains the tray menu, only rebuilding it when what it displays has changed.
+pub struct TrayMenu<R: Runtime = Wry> {
+    app_handle: AppHandle<R>,
+    menu_state: Mutex<TrayMenuState>,
+}
+
+impl<R: Runtime> TrayMenu<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
+        TrayMenu {
+            app_handle,
+            menu_state: Mutex::new(TrayMenuState::default()),
+        }
+    }
+
+    pub fn update_profiles(&self, profiles: &ServerProfiles) {
+        self.update(|menu_state| menu_state.set_profiles(profiles));
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut TrayMenuState)) {
+        let mut menu_state = self.menu_state.lock().unwrap();
+        let mut new_menu_state = menu_state.clone();
+        apply(&mut new_menu_state);
+
+        if *menu_state == new_menu_state {
+            return;
+        }
+
+        if let Err(e) = self.app_handle.tray_handle().set_menu(build_tray_menu(&new_menu_state)) {
+            warn!("Could not update the tray menu: {:?}", e);
+            return;
+        }
+
+        *menu_state = new_menu_state;
+    }
+}
+
+impl<R: Runtime> StateSink for TrayMenu<R> {
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.update(|menu_state| menu_state.set_vibin_state(vibin_state));
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Everything the tray tooltip and title display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrayStatus {
+    pub connection: VibinConnectionState,
+    pub is_reconnecting: bool,
+    pub is_standby: bool,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub play_state: Option<PlayState>,
+}
+
+impl Default for TrayStatus {
+    fn default() -> Self {
+        TrayStatus {
+            connection: VibinConnectionState::Disconnected(None),
+            is_reconnecting: false,
+            is_standby: false,
+            artist: None,
+            title: None,
+            play_state: None,
+        }
+    }
+}
+
+impl TrayStatus {
+    pub fn set_app_state(&mut self, app_state: &AppState) {
+        self.connection = app_state.vibin_connection.clone();
+        self.is_reconnecting = app_state.reconnect.is_some();
+    }
+
+    pub fn set_vibin_state(&mut self, vibin_state: &VibinState) {
+        let active_track = vibin_state.active_track.as_ref();
+
+        self.is_standby = !vibin_state.is_streamer_power_on();
+        self.artist = active_track.and_then(|track| track.artist.clone());
+        self.title = active_track.and_then(|track| track.title.clone());
+        self.play_state =
+            vibin_state.transport.as_ref().and_then(|transport| transport.play_state.clone());
+    }
+
+    /// e.g. "Massive Attack — Teardrop (paused)", or "Disconnected: Timed out connecting to ...".
+    pub fn tooltip(&self) -> String {
+        match &self.connection {
+            VibinConnectionState::Connected(_) => match (&self.title, &self.artist) {
+                _ if self.is_standby => String::from("Standby"),
+                (Some(title), artist) => {
+                    let track = match artist {
+                        Some(artist) => format!("{artist} — {title}"),
+                        None => title.clone(),
+                    };
+
+                    match self.play_state {
+                        Some(PlayState::Pause) => format!("{track} (paused)"),
+                        Some(PlayState::Buffering) => format!("{track} (buffering)"),
+                        Some(PlayState::Stop) => format!("{track} (stopped)"),
+                        _ => track,
+                    }
+                }
+                (None, _) => String::from("Nothing playing"),
+            },
+            VibinConnectionState::Connecting(host) => format!("Connecting to {host}…"),
+            VibinConnectionState::Disconnecting => String::from("Disconnecting…"),
+            VibinConnectionState::Disconnected(message) => {
+                let retrying = if self.is_reconnecting { " (retrying)" } else { "" };
+
+                match message {
+                    Some(message) => format!("Disconnected: {message}{retrying}"),
+                    None => format!("Disconnected{retrying}"),
+                }
+            }
+        }
+    }
+
+    /// The (shortened) current track title, shown next to the tray icon on macOS. Empty when
+    /// nothing is playing.
+    pub fn title(&self) -> String {
+        let is_active = matches!(self.play_state, Some(PlayState::Play | PlayState::Pause));
+
+        match (&self.connection, &self.title) {
+            (VibinConnectionState::Connected(_), Some(title)) if is_active && !self.is_standby => {
+                if title.chars().count() <= TRAY_TITLE_MAX_CHARS {
+                    return title.clone();
+                }
+
+                let shortened: String = title.chars().take(TRAY_TITLE_MAX_CHARS - 1).collect();
+                format!("{}…", shortened.trim_end())
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// The tray icon variants. Each is the application icon with a small badge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayIcon {
+    Disconnected,
+    Connecting,
+    Standby,
+    Idle,
+    Playing,
+    Paused,
+    Buffering,
+}
+
+impl TrayIcon {
+    pub fn for_status(status: &TrayStatus) -> TrayIcon {
+        match status.connection {
+            VibinConnectionState::Connected(_) if status.is_standby => TrayIcon::Standby,
+            VibinConnectionState::Connected(_) => match status.play_state {
+                Some(PlayState::Play) => TrayIcon::Playing,
+                Some(PlayState::Pause) => TrayIcon::Paused,
+                Some(PlayState::Buffering) => TrayIcon::Buffering,
+                _ => TrayIcon::Idle,
+            },
+            VibinConnectionState::Connecting(_) => TrayIcon::Connecting,
+            VibinConnectionState::Disconnected(_) | VibinConnectionState::Disconnecting => {
+                TrayIcon::Disconnected
+            }
+        }
+    }
+
+    fn image(&self) -> Icon {
+        let bytes: &[u8] = match self {
+            TrayIcon::Disconnected => include_bytes!("../icons/tray/disconnected.png"),
+            TrayIcon::Connecting => include_bytes!("../icons/tray/connecting.png"),
+            TrayIcon::Standby => include_bytes!("../icons/tray/standby.png"),
+            TrayIcon::Idle => include_bytes!("../icons/icon.png"),
+            TrayIcon::Playing => include_bytes!("../icons/tray/playing.png"),
+            TrayIcon::Paused => include_bytes!("../icons/tray/paused.png"),
+            TrayIcon::Buffering => include_bytes!("../icons/tray/buffering.png"),
+        };
+
+        Icon::Raw(bytes.to_vec())
+    }
+}
+
+struct TrayStatusDisplayState {
+    status: TrayStatus,
+    shown: Option<(String, String)>,
+    shown_icon: Option<TrayIcon>,
+    last_shown_at: Option<Instant>,
+    buffering_since: Option<Instant>,
+    is_update_scheduled: bool,
+}
+
+/// Keeps the tray icon, tooltip, and title up to date. Updates are throttled: changes arriving
+/// within TRAY_STATUS_MIN_INTERVAL of the last update are combined into a single later update.
+pub struct TrayStatusDisplay<R: Runtime = Wry> {
+    app_handle: AppHandle<R>,
+    state: Arc<Mutex<TrayStatusDisplayState>>,
+}
+
+impl<R: Runtime> Clone for TrayStatusDisplay<R> {
+    fn clone(&self) -> Self {
+        TrayStatusDisplay { app_handle: self.app_handle.clone(), state: self.state.clone() }
+    }
+}
+
+impl<R: Runtime> TrayStatusDisplay<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
+        TrayStatusDisplay {
+            app_handle,
+            state: Arc::new(Mutex::new(TrayStatusDisplayState {
+                status: TrayStatus::default(),
+                shown: None,
+                shown_icon: None,
+                last_shown_at: None,
+                buffering_since: None,
+                is_update_scheduled: false,
+            })),
+        }
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut TrayStatus)) {
+        let mut state = self.state.lock().unwrap();
+        apply(&mut state.status);
+
+        state.buffering_since = match TrayIcon::for_status(&state.status) {
+            TrayIcon::Buffering => state.buffering_since.or(Some(Instant::now())),
+            _ => None,
+        };
+
+        // A scheduled update will show the latest status when it runs.
+        if state.is_update_scheduled {
+            return;
+        }
+
+        let wait = state
+            .last_shown_at
+            .map(|shown_at| TRAY_STATUS_MIN_INTERVAL.saturating_sub(shown_at.elapsed()))
+            .unwrap_or_default();
+
+        match wait.is_zero() {
+            true => self.show(&mut state),
+            false => self.schedule(&mut state, wait),
+        }
+    }
+
+    fn schedule(&self, state: &mut TrayStatusDisplayState, wait: Duration) {
+        if state.is_update_scheduled {
+            return;
+        }
+
+        state.is_update_scheduled = true;
+        let display = self.clone();
+
+        tauri::async_runtime::spawn(async move {
+            sleep(wait).await;
+
+            let mut state = display.state.lock().unwrap();
+            state.is_update_scheduled = false;
+            display.show(&mut state);
+        });
+    }
+
+    fn show(&self, state: &mut TrayStatusDisplayState) {
+        let tray_handle = self.app_handle.tray_handle();
+        let shown = (state.status.tooltip(), state.status.title());
+
+        // The streamer buffers briefly between tracks, so buffering is only shown once it has
+        // lasted BUFFERING_ICON_DELAY. Until then the current icon is left alone.
+        let buffering_wait = state
+            .buffering_since
+            .map(|since| BUFFERING_ICON_DELAY.saturating_sub(since.elapsed()))
+            .filter(|wait| !wait.is_zero());
+
+        let icon = match TrayIcon::for_status(&state.status) {
+            TrayIcon::Buffering if buffering_wait.is_some() => {
+                state.shown_icon.unwrap_or(TrayIcon::Playing)
+            }
+            icon => icon,
+        };
+
+        if state.shown.as_ref() != Some(&shown) {
+            if let Err(e) = tray_handle.set_tooltip(&shown.0) {
+                warn!("Could not update the tray tooltip: {:?}", e);
+            }
+
+            #[cfg(target_os = "macos")]
+            if let Err(e) = tray_handle.set_title(&shown.1) {
+                warn!("Could not update the tray title: {:?}", e);
+            }
+
+            state.shown = Some(shown);
+            state.last_shown_at = Some(Instant::now());
+        }
+
+        if state.shown_icon != Some(icon) {
+            if let Err(e) = tray_handle.set_icon(icon.image()) {
+                warn!("Could not update the tray icon: {:?}", e);
+            }
+
+            // Setting the icon resets its template status (see systemTray.iconAsTemplate).
+            #[cfg(target_os = "macos")]
+            if let Err(e) = tray_handle.set_icon_as_template(true) {
+                warn!("Could not set the tray icon as a template: {:?}", e);
+            }
+
+            state.shown_icon = Some(icon);
+            state.last_shown_at = Some(Instant::now());
+        }
+
+        if let Some(wait) = buffering_wait {
+            self.schedule(state, wait);
+        }
+    }
+}
+
+impl<R: Runtime> StateSink for TrayStatusDisplay<R> {
+    fn emit_app_state(&self, app_state: &AppState) {
+        self.update(|status| status.set_app_state(app_state));
+    }
+
+    fn emit_vibin_state(&self, vibin_state: &VibinState) {
+        self.update(|status| status.set_vibin_state(vibin_state));
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::profiles::{ServerProfile, ServerProfiles};
+    use crate::state::{
+        Amplifier, AppState, PlayState, PowerState, Source, TransportState, VibinConnectionState,
+        VibinState,
+    };
+    use crate::tray::{play_pause_action, TrayIcon, TrayMenuState, TraySource, TrayStatus};
+    use crate::vibin_api::TransportAction;
+
+    fn source(id: &str, name: &str) -> Source {
+        serde_json::from_value(json!({ "id": id, "name": name, "ui_selectable": true })).unwrap()
+    }
+
+    fn transport(play_state: PlayState, active_controls: &[&str]) -> TransportState {
+        TransportState {
+            play_state: Some(play_state),
+            active_controls: active_controls.iter().map(|control| control.to_string()).collect(),
+            repeat: None,
+            shuffle: None,
+        }
+    }
+
+    #[test]
+    fn it_chooses_the_play_pause_action() {
+        assert_eq!(
+            play_pause_action(&transport(PlayState::Play, &["pause", "toggle_playback"])),
+            Some(TransportAction::Pause)
+        );
+        assert_eq!(
+            play_pause_action(&transport(PlayState::Play, &["stop"])),
+            Some(TransportAction::Stop)
+        );
+        assert_eq!(
+            play_pause_action(&transport(PlayState::Pause, &["toggle_playback"])),
+            Some(TransportAction::TogglePlayback)
+        );
+        assert_eq!(play_pause_action(&transport(PlayState::Stop, &["next"])), None);
+    }
+
+    #[test]
+    fn it_reflects_now_playing_and_controls() {
+        let track = json!({ "title": "Teardrop", "artist": "Massive Attack" });
+
+        let mut vibin_state = VibinState::new();
+        vibin_state.active_track = Some(serde_json::from_value(track).unwrap());
+        vibin_state.transport = Some(transport(PlayState::Play, &["pause", "next"]));
+        vibin_state.amplifier = Some(Amplifier { mute: Some("on".into()), volume: Some(0.4) });
+
+        let mut menu_state = TrayMenuState::default();
+        menu_state.set_vibin_state(&vibin_state);
+
+        assert_eq!(menu_state.now_playing.as_deref(), Some("Teardrop — Massive Attack"));
+        assert!(menu_state.is_playing);
+        assert_eq!(menu_state.play_pause_action, Some(TransportAction::Pause));
+        assert!(menu_state.can_next);
+        assert!(!menu_state.can_previous);
+        assert_eq!(menu_state.is_muted, Some(true));
+        assert!(!menu_state.is_power_on);
+    }
+
+    #[test]
+    fn it_reflects_sources_and_active_source() {
+        let mut vibin_state = VibinState::new();
+        vibin_state.sources = vec![
+            source("MEDIA_PLAYER", "Media Library"),
+            source("AIRPLAY", "AirPlay"),
+        ];
+        vibin_state.source = Some(source("AIRPLAY", "AirPlay"));
+
+        let mut menu_state = TrayMenuState::default();
+        menu_state.set_vibin_state(&vibin_state);
+
+        assert_eq!(
+            menu_state.sources,
+            vec![
+                TraySource { id: "MEDIA_PLAYER".into(), name: "Media Library".into() },
+                TraySource { id: "AIRPLAY".into(), name: "AirPlay".into() },
+            ]
+        );
+        assert_eq!(menu_state.active_source_id.as_deref(), Some("AIRPLAY"));
+    }
+
+    #[test]
+    fn it_is_unchanged_by_unrelated_state() {
+        let mut vibin_state = VibinState::new();
+        vibin_state.sources = vec![source("MEDIA_PLAYER", "Media Library")];
+
+        let mut menu_state = TrayMenuState::default();
+        menu_state.set_vibin_state(&vibin_state);
+        let before = menu_state.clone();
+
+        vibin_state.display.line1 = Some("Teardrop".into());
+        menu_state.set_vibin_state(&vibin_state);
+
+        assert_eq!(menu_state, before);
+    }
+
+    #[test]
+    fn it_reflects_profiles_and_active_profile() {
+        let profiles = ServerProfiles {
+            profiles: vec![
+                ServerProfile { name: "Lounge".into(), host: "ws://lounge:8080/ws".into() },
+                ServerProfile { name: "Study".into(), host: "ws://study:8080/ws".into() },
+            ],
+            active: Some("Study".into()),
+        };
+
+        let mut menu_state = TrayMenuState::default();
+        menu_state.set_profiles(&profiles);
+
+        assert_eq!(menu_state.profile_names, vec!["Lounge", "Study"]);
+        assert_eq!(menu_state.active_profile.as_deref(), Some("Study"));
+    }
+
+    #[test]
+    fn it_describes_the_connection_and_current_track() {
+        let mut status = TrayStatus::default();
+        let mut app_state = AppState::new();
+
+        app_state.set_disconnected(Some("Timed out connecting to vibin.local".into()));
+        status.set_app_state(&app_state);
+
+        assert_eq!(status.tooltip(), "Disconnected: Timed out connecting to vibin.local");
+        assert_eq!(status.title(), "");
+
+        app_state.vibin_connection = VibinConnectionState::Connected("ws://vibin.local".into());
+        status.set_app_state(&app_state);
+
+        let mut vibin_state = VibinState::new();
+        vibin_state.streamer_power = Some(PowerState::On);
+        vibin_state.active_track = Some(
+            serde_json::from_value(json!({ "title": "Teardrop", "artist": "Massive Attack" }))
+                .unwrap(),
+        );
+        vibin_state.transport = Some(transport(PlayState::Pause, &["play"]));
+        status.set_vibin_state(&vibin_state);
+
+        assert_eq!(status.tooltip(), "Massive Attack — Teardrop (paused)");
+        assert_eq!(status.title(), "Teardrop");
+
+        vibin_state.streamer_power = Some(PowerState::Off);
+        status.set_vibin_state(&vibin_state);
+
+        assert_eq!(status.tooltip(), "Standby");
+        assert_eq!(status.title(), "");
+    }
+
+    #[test]
+    fn it_shortens_long_titles() {
+        let status = TrayStatus {
+            connection: VibinConnectionState::Connected("ws://vibin.local".into()),
+            title: Some("Everything In Its Right Place (Live in France)".into()),
+            play_state: Some(PlayState::Play),
+            ..TrayStatus::default()
+        };
+
+        assert_eq!(status.title(), "Everything In Its Right…");
+    }
+
+    #[test]
+    fn it_chooses_the_tray_icon() {
+        let mut status = TrayStatus::default();
+
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Disconnected);
+
+        status.connection = VibinConnectionState::Connecting("ws://vibin.local".into());
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Connecting);
+
+        status.connection = VibinConnectionState::Connected("ws://vibin.local".into());
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Idle);
+
+        status.play_state = Some(PlayState::Buffering);
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Buffering);
+
+        status.play_state = Some(PlayState::Pause);
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Paused);
+
+        status.is_standby = true;
+        assert_eq!(TrayIcon::for_status(&status), TrayIcon::Standby);
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::state::ActiveTrack;
+
+// A typed client for the Vibin REST API. Vibin serves its REST API from the same host and port
+// as its WebSocket server, under /api. Each call returns a VibinApiError on failure, which allows
+// the caller to report the problem to the UI rather than silently dropping it.
+
+const REQUEST_TIMEOUT_SECS: u64 = 5;
+
+// ------------------------------------------------------------------------------------------------
+// Actions
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerAction {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportAction {
+    Next,
+    Pause,
+    Play,
+    Previous,
+    Repeat,
+    Shuffle,
+    Stop,
+    TogglePlayback,
+}
+
+impl TransportAction {
+    fn endpoint(&self) -> &'static str {
+        match self {
+            TransportAction::Next => "/transport/next",
+            TransportAction::Pause => "/transport/pause",
+            TransportAction::Play => "/transport/play",
+            TransportAction::Previous => "/transport/previous",
+            TransportAction::Repeat => "/transport/repeat",
+            TransportAction::Shuffle => "/transport/shuffle",
+            TransportAction::Stop => "/transport/stop",
+            TransportAction::TogglePlayback => "/transport/toggle_playback",
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Errors
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "detail")]
+pub enum VibinApiError {
+    HostNotSet,
+    InvalidHost(String),
+    Timeout,
+    Http { status: u16, message: String },
+    Request(String),
+}
+
+impl fmt::Display for VibinApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VibinApiError::HostNotSet => write!(f, "Vibin host has not been set"),
+            VibinApiError::InvalidHost(e) => write!(f, "Invalid Vibin host: {e}"),
+            VibinApiError::Timeout => write!(f, "Timed out waiting for Vibin"),
+            VibinApiError::Http { status, message } => {
+                write!(f, "Vibin responded with HTTP {status}: {message}")
+            }
+            VibinApiError::Request(e) => write!(f, "Vibin request failed: {e}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for VibinApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            VibinApiError::Timeout
+        } else {
+            VibinApiError::Request(e.to_string())
+        }
+    }
+}
+
+/// Vibin (FastAPI) error responses look like {"detail": "..."}.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    detail: serde_json::Value,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+/// Derive the Vibin REST API base URL from the Vibin WebSocket URL. e.g.
+/// ws://vibin.local:8080/ws -> http://vibin.local:8080/api
+pub fn api_base_url(vibin_ws_url: &str) -> Result<Url, VibinApiError> {
+    let mut url = Url::parse(vibin_ws_url).map_err(|e| VibinApiError::InvalidHost(e.to_string()))?;
+
+    let scheme = match url.scheme() {
+        "ws" | "http" => "http",
+        "wss" | "https" => "https",
+        other => return Err(VibinApiError::InvalidHost(format!("Unsupported scheme: {other}"))),
+    };
+
+    url.set_scheme(scheme)
+        .map_err(|_| VibinApiError::InvalidHost(format!("Could not use scheme: {scheme}")))?;
+    url.set_path("/api");
+    url.set_query(None);
+
+    Ok(url)
+}
+
+/// The vibinui (full Vibin Web UI) URL for the given track: the track itself if it has a media id,
+/// otherwise its album, otherwise vibinui's current track screen. vibinui is served by Vibin, so
+/// the URL is relative to the API base URL.
+pub fn vibinui_url(api_base_url: &Url, active_track: Option<&ActiveTrack>) -> Url {
+    let path = match active_track {
+        Some(ActiveTrack { track_media_id: Some(track_media_id), .. }) => {
+            format!("/ui/tracks/{track_media_id}")
+        }
+        Some(ActiveTrack { album_media_id: Some(album_media_id), .. }) => {
+            format!("/ui/albums/{album_media_id}")
+        }
+        _ => String::from("/ui/current"),
+    };
+
+    let mut url = api_base_url.clone();
+    url.set_path(&path);
+
+    url
+}
+
+#[derive(Clone)]
+pub struct VibinApiClient {
+    http: Client,
+    base_url: Option<Url>,
+}
+
+impl VibinApiClient {
+    pub fn new() -> Self {
+        VibinApiClient {
+            http: Client::builder()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .build()
+                .unwrap_or_default(),
+            base_url: None,
+        }
+    }
+
+    /// Point the client at the Vibin host for the given WebSocket URL.
+    pub fn set_vibin_host(&mut self, vibin_ws_url: &str) -> Result<(), VibinApiError> {
+        self.base_url = Some(api_base_url(vibin_ws_url)?);
+
+        Ok(())
+    }
+
+    pub fn base_url(&self) -> Option<&Url> {
+        self.base_url.as_ref()
+    }
+
+    // System
+
+    pub async fn power(&self, action: PowerAction) -> Result<(), VibinApiError> {
+        match action {
+            PowerAction::On => self.post("/system/power/on", &[]).await,
+            PowerAction::Off => self.post("/system/power/off", &[]).await,
+            PowerAction::Toggle => self.post("/system/power/toggle", &[]).await,
+        }
+    }
+
+    /// Source names (e.g. "Internet Radio") are percent-encoded as a single path segment.
+    pub async fn set_source(&self, source_name: &str) -> Result<(), VibinApiError> {
+        let mut url = self.endpoint_url("/system/streamer/audio_source")?;
+        url.path_segments_mut()
+            .map_err(|_| VibinApiError::InvalidHost(String::from("URL cannot be a base")))?
+            .push(source_name);
+
+        self.post_url(url, &[]).await
+    }
+
+    // Transport
+
+    pub async fn transport(&self, action: TransportAction) -> Result<(), VibinApiError> {
+        self.post(action.endpoint(), &[]).await
+    }
+
+    pub async fn seek(&self, target: f64) -> Result<(), VibinApiError> {
+        self.post("/transport/seek", &[("target", target.to_string())]).await
+    }
+
+    // Queue
+
+    /// Play the queue entry with the given id (see QueueEntry::id).
+    pub async fn queue_play(&self, entry_id: isize) -> Result<(), VibinApiError> {
+        self.post(&format!("/queue/play/id/{entry_id}"), &[]).await
+    }
+
+    pub async fn queue_delete(&self, entry_id: isize) -> Result<(), VibinApiError> {
+        self.post(&format!("/queue/delete/{entry_id}"), &[]).await
+    }
+
+    /// Move the queue entry with the given id from one position in the queue to another.
+    pub async fn queue_move(
+        &self,
+        entry_id: isize,
+        from_position: usize,
+        to_position: usize,
+    ) -> Result<(), VibinApiError> {
+        self.post(&format!("/queue/move/{entry_id}/{from_position}/{to_position}"), &[]).await
+    }
+
+    // Amplifier
+
+    pub async fn toggle_mute(&self) -> Result<(), VibinApiError> {
+        self.post("/system/amplifier/mute/toggle", &[]).await
+    }
+
+    pub async fn volume_up(&self) -> Result<(), VibinApiError> {
+        self.post("/system/amplifier/volume/up", &[]).await
+    }
+
+    pub async fn volume_down(&self) -> Result<(), VibinApiError> {
+        self.post("/system/amplifier/volume/down", &[]).await
+    }
+
+    pub async fn set_volume(&self, level: f32) -> Result<(), VibinApiError> {
+        self.post(&format!("/system/amplifier/volume/{level}"), &[]).await
+    }
+
+    fn endpoint_url(&self, endpoint: &str) -> Result<Url, VibinApiError> {
+        let base_url = self.base_url.as_ref().ok_or(VibinApiError::HostNotSet)?;
+
+        Url::parse(&format!("{}{}", base_url.as_str().trim_end_matches('/'), endpoint))
+            .map_err(|e| VibinApiError::InvalidHost(e.to_string()))
+    }
+
+    async fn post(&self, endpoint: &str, query: &[(&str, String)]) -> Result<(), VibinApiError> {
+        self.post_url(self.endpoint_url(endpoint)?, query).await
+    }
+
+    async fn post_url(&self, url: Url, query: &[(&str, String)]) -> Result<(), VibinApiError> {
+        let response = self.http.post(url).query(query).send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(ErrorResponse { detail: serde_json::Value::String(detail) }) => detail,
+            Ok(ErrorResponse { detail }) => detail.to_string(),
+            Err(_) if !body.is_empty() => body,
+            Err(_) => status.canonical_reason().unwrap_or("Unknown error").to_string(),
+        };
+
+        Err(VibinApiError::Http { status: status.as_u16(), message })
+    }
+}
+
+impl Default for VibinApiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type VibinApiClientMutex = Arc<Mutex<VibinApiClient>>;
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::state::ActiveTrack;
+    use crate::vibin_api::{api_base_url, vibinui_url, VibinApiClient, VibinApiError};
+
+    #[test]
+    fn it_derives_api_url_from_websocket_url() {
+        assert_eq!(
+            api_base_url("ws://vibin.local:8080/ws").unwrap().as_str(),
+            "http://vibin.local:8080/api"
+        );
+        assert_eq!(
+            api_base_url("wss://vibin.example.com/ws").unwrap().as_str(),
+            "https://vibin.example.com/api"
+        );
+    }
+
+    #[test]
+    fn it_rejects_unsupported_hosts() {
+        assert!(matches!(api_base_url("not a url"), Err(VibinApiError::InvalidHost(_))));
+        assert!(matches!(api_base_url("ftp://vibin.local"), Err(VibinApiError::InvalidHost(_))));
+    }
+
+    #[test]
+    fn it_links_to_the_track_in_vibinui() {
+        let api_url = api_base_url("ws://vibin.local:8080/ws").unwrap();
+        let mut track = ActiveTrack {
+            title: None,
+            artist: None,
+            album: None,
+            art_url: None,
+            duration: None,
+            album_media_id: Some("album-1".into()),
+            track_media_id: Some("track-1".into()),
+            stream_url: None,
+        };
+
+        assert_eq!(
+            vibinui_url(&api_url, Some(&track)).as_str(),
+            "http://vibin.local:8080/ui/tracks/track-1"
+        );
+
+        track.track_media_id = None;
+
+        assert_eq!(
+            vibinui_url(&api_url, Some(&track)).as_str(),
+            "http://vibin.local:8080/ui/albums/album-1"
+        );
+        assert_eq!(vibinui_url(&api_url, None).as_str(), "http://vibin.local:8080/ui/current");
+    }
+
+    #[tokio::test]
+    async fn it_requires_a_host() {
+        let client = VibinApiClient::new();
+
+        assert_eq!(client.toggle_mute().await, Err(VibinApiError::HostNotSet));
+    }
+}
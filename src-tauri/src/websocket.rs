@@ -1,29 +1,43 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
-use futures_util::StreamExt;
+use futures_util::{SinkExt, StreamExt};
 use log::{info, warn, error};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tauri::async_runtime::Mutex as TauriMutex;
-use tauri::{AppHandle, Manager};
 use tokio;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
 use tokio::time::{Duration, sleep, timeout};
 use tokio_tungstenite::connect_async;
 use tungstenite;
 
 use crate::average::RunningAverage;
+use crate::backoff::{Backoff, ReconnectPolicy};
+use crate::recording::{read_recording, RecordedMessage, SessionRecorder};
+use crate::sink::StateSinkRef;
 use crate::state::{
     ActiveTrack,
     Amplifier,
-    AppState,
+    AppError,
+    AppErrorCategory,
     AppStateMutex,
+    AudioFormat,
+    PlayState,
     Position,
+    PowerState,
+    Queue,
+    QueueEntry,
+    RepeatState,
+    ShuffleState,
+    Source,
     StreamerDisplay,
     StreamerSources,
     TransportState,
     VibinConnectionState::{Connected, Connecting, Disconnected, Disconnecting},
     VibinStateMutex,
-    WeeVibinMessage,
 };
 
 // ------------------------------------------------------------------------------------------------
@@ -61,7 +75,7 @@ struct VibinMessage {
 
 #[derive(Deserialize)]
 struct StreamerPayload {
-    power: Option<String>,
+    power: Option<PowerState>,
     sources: Option<StreamerSources>,
     display: Option<StreamerDisplay>,
 }
@@ -74,7 +88,7 @@ struct AmplifierPayload {
 
 #[derive(Deserialize)]
 struct SystemPayload {
-    power: Option<String>,
+    power: Option<PowerState>,
     streamer: StreamerPayload,
     amplifier: Option<AmplifierPayload>,
 }
@@ -84,10 +98,10 @@ struct SystemPayload {
 
 #[derive(Serialize, Deserialize)]
 struct TransportStatePayload {
-    pub play_state: Option<String>,
+    pub play_state: Option<PlayState>,
     pub active_controls: Vec<String>,
-    pub repeat: Option<String>,
-    pub shuffle: Option<String>,
+    pub repeat: Option<RepeatState>,
+    pub shuffle: Option<ShuffleState>,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -138,64 +152,80 @@ struct CurrentlyPlayingPayload {
 }
 
 // ------------------------------------------------------------------------------------------------
+// Queue message
 
-#[derive(Clone, Serialize)]
-pub enum AppErrorCategory {
-    WebSocket,
+#[derive(Serialize, Deserialize)]
+struct QueueItemMetadataWS {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<isize>,
 }
 
-#[derive(Clone, Serialize)]
-pub struct AppError {
-    pub category: AppErrorCategory,
-    pub message: String,
+#[derive(Serialize, Deserialize)]
+struct QueueItemWS {
+    pub id: isize,
+    pub position: usize,
+    #[serde(rename = "albumArtURI")]
+    pub album_art_uri: Option<String>,
+    pub metadata: Option<QueueItemMetadataWS>,
 }
 
-// ------------------------------------------------------------------------------------------------
-
-trait CustomEmitters {
-    fn emit_app_state(&self, app_state: &AppState);
-    fn emit_websocket_error(&self, error_message: &str);
+#[derive(Serialize, Deserialize)]
+struct QueuePayload {
+    pub play_position: Option<usize>,
+    #[serde(default)]
+    pub items: Vec<QueueItemWS>,
 }
 
-impl CustomEmitters for AppHandle {
-    fn emit_app_state(&self, app_state: &AppState) {
-        self.emit_all(&WeeVibinMessage::AppState.to_string(), app_state).unwrap();
-    }
+// ------------------------------------------------------------------------------------------------
+// Outgoing command message
+//
+// Commands sent to Vibin over the existing WebSocket connection. These mirror the shape of the
+// incoming VibinMessage.
 
-    fn emit_websocket_error(&self, error_message: &str) {
-        self.emit_all(
-            &WeeVibinMessage::Error.to_string(),
-            AppError {
-                category: AppErrorCategory::WebSocket,
-                message: error_message.into(),
-            },
-        )
-        .unwrap();
-    }
+#[derive(Serialize, Deserialize)]
+pub struct VibinCommand {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub payload: serde_json::Value,
 }
 
+/// Sends messages over the write half of the current WebSocket connection.
+pub type VibinCommandSender = UnboundedSender<tungstenite::Message>;
+
 // ------------------------------------------------------------------------------------------------
 
 enum VibinWebSocketError {
     WebSocketError(tungstenite::Error),
     CustomError(String),
+    InvalidHostError(String),
     ClientLostConnectionError,
     ServerClosedConnectionError,
 }
 
 // ------------------------------------------------------------------------------------------------
 
+// The manager and connection emit state changes and errors to a StateSink, rather than directly
+// to Tauri. This allows them to be run (and tested) without a Tauri runtime.
+
 #[derive(Clone)]
 pub struct WebSocketManager {
     pub vibin_host: Option<Box<String>>,
     pub stop_flag: Arc<Mutex<bool>>,
     pub app_state_mutex: AppStateMutex,
     pub vibin_state_mutex: VibinStateMutex,
-    pub app_handle: AppHandle,
+    pub sink: StateSinkRef,
 
     pub connection: Arc<TauriMutex<WebSocketConnection>>,
     pub is_started: Arc<Mutex<bool>>,
     pub have_connected: Arc<Mutex<bool>>,
+    pub startup_retry: Arc<Mutex<bool>>,
+    pub reconnect_policy: ReconnectPolicy,
+    pub connect_timeout: Duration,
+    pub command_sender: Arc<Mutex<Option<VibinCommandSender>>>,
+    pub recording_dir: Arc<Mutex<Option<PathBuf>>>,
+    pub message_parse_errors: Arc<AtomicUsize>,
 }
 
 impl WebSocketManager {
@@ -204,28 +234,70 @@ impl WebSocketManager {
         stop_flag: Arc<Mutex<bool>>,
         app_state_mutex: AppStateMutex,
         vibin_state_mutex: VibinStateMutex,
-        app_handle: AppHandle,
+        sink: StateSinkRef,
     ) -> Self {
+        let connection = WebSocketConnection::new();
+        let message_parse_errors = connection.message_parse_errors.clone();
+
         WebSocketManager {
             vibin_host,
             stop_flag,
             app_state_mutex,
             vibin_state_mutex,
-            app_handle,
+            sink,
 
-            connection: Arc::new(TauriMutex::new(WebSocketConnection {
-                stop_flag: None,
-                vibin_host: String::from(""),
-            })),
+            connection: Arc::new(TauriMutex::new(connection)),
             is_started: Arc::new(Mutex::new(false)),
             have_connected: Arc::new(Mutex::new(false)),
+            startup_retry: Arc::new(Mutex::new(false)),
+            reconnect_policy: ReconnectPolicy::default(),
+            connect_timeout: Duration::from_secs(5),
+            command_sender: Arc::new(Mutex::new(None)),
+            recording_dir: Arc::new(Mutex::new(None)),
+            message_parse_errors,
         }
     }
 
+    /// The number of Vibin messages which could not be parsed since weevibin was started.
+    pub fn message_parse_error_count(&self) -> usize {
+        self.message_parse_errors.load(Ordering::SeqCst)
+    }
+
     pub fn set_have_connected(&mut self, have_connected: bool) {
         *self.have_connected.lock().unwrap() = have_connected;
     }
 
+    /// Enable "startup retry" mode. Normally a connection is only retried if it was previously
+    /// successful; in startup retry mode failed connections are retried (with backoff) even if
+    /// the host has never been reached. This allows weevibin to be launched before the Vibin
+    /// server is available (e.g. at login, before Wi-Fi is up). Invalid hosts are never retried.
+    pub fn set_startup_retry(&mut self, startup_retry: bool) {
+        *self.startup_retry.lock().unwrap() = startup_retry;
+    }
+
+    /// Record every message received from Vibin to a new file in `recording_dir`, starting with
+    /// the next connection. Recording is disabled when `recording_dir` is None.
+    pub fn set_recording_dir(&mut self, recording_dir: Option<PathBuf>) {
+        *self.recording_dir.lock().unwrap() = recording_dir;
+    }
+
+    /// Send a command to Vibin over the current WebSocket connection.
+    pub fn send_command(&self, command: &VibinCommand) -> Result<(), String> {
+        let message = serde_json::to_string(command)
+            .map_err(|e| format!("Could not serialize Vibin command: {:?}", e))?;
+
+        match self.command_sender.lock().unwrap().as_ref() {
+            Some(sender) => sender
+                .send(tungstenite::Message::Text(message))
+                .map_err(|_| String::from("WebSocket connection has closed")),
+            None => Err(String::from("Not connected to Vibin")),
+        }
+    }
+
+    fn should_retry(&self) -> bool {
+        *self.have_connected.lock().unwrap() || *self.startup_retry.lock().unwrap()
+    }
+
     pub fn start(&mut self) {
         if *self.is_started.lock().unwrap() == true {
             warn!("WebSocketManager is already started; ignoring start request");
@@ -266,7 +338,7 @@ impl WebSocketManager {
                     &self_clone.stop_flag.clone(),
                     &self_clone.app_state_mutex,
                     &self_clone.vibin_state_mutex,
-                    self_clone.app_handle.clone(),
+                    self_clone.sink.clone(),
                     self_clone_for_connection,
                 )
                 .await;
@@ -275,42 +347,50 @@ impl WebSocketManager {
 
             // self_clone.app_state_mutex.lock().unwrap().vibin_connection = Disconnected(None);
             *self_clone.is_started.lock().unwrap() = false;
-            self_clone.app_handle.emit_app_state(&self_clone.app_state_mutex.lock().unwrap());
+            self_clone.sink.emit_app_state(&self_clone.app_state_mutex.lock().unwrap());
         });
     }
 
     pub async fn stop(&mut self) {
-        match self.app_state_mutex.lock().unwrap().vibin_connection {
-            Connected(_) => {},
-            _ => {
-                warn!("WebSocketManager not connected; ignoring stop() request");
-                return;
-            }
+        let is_connected = matches!(
+            self.app_state_mutex.lock().unwrap().vibin_connection,
+            Connected(_)
+        );
+
+        // A started manager which isn't connected is waiting to retry its connection. It still
+        // needs to be stopped, otherwise it would keep retrying the old host.
+        if !is_connected && !*self.is_started.lock().unwrap() {
+            warn!("WebSocketManager not connected; ignoring stop() request");
+            return;
         }
 
         info!("WebSocketManager requesting WebSocketConnection disconnect");
 
-        self.app_state_mutex.lock().unwrap().vibin_connection = Disconnecting;
-        self.app_handle.emit_app_state(&self.app_state_mutex.lock().unwrap());
+        if is_connected {
+            self.app_state_mutex.lock().unwrap().vibin_connection = Disconnecting;
+            self.sink.emit_app_state(&self.app_state_mutex.lock().unwrap());
+        }
+
         *self.stop_flag.lock().unwrap() = true;
 
         info!("WebSocketManager waiting for disconnect");
 
-        let mut is_disconnected = false;
-
-        while !is_disconnected {
-            let connection_state = self.app_state_mutex.lock().unwrap().vibin_connection.clone();
-            match connection_state {
-                Disconnected(_) => is_disconnected = true,
-                _ => sleep(Duration::from_millis(100)).await,
-            }
+        // The connection task clears is_started once WebSocketConnection::start() has returned.
+        while *self.is_started.lock().unwrap() == true {
+            sleep(Duration::from_millis(100)).await;
         }
 
         info!("WebSocketManager has detected WebSocketConnection disconnect");
 
-        self.app_state_mutex.lock().unwrap().vibin_connection = Disconnected(None);
+        {
+            let mut app_state = self.app_state_mutex.lock().unwrap();
+            app_state.vibin_connection = Disconnected(None);
+            app_state.reconnect = None;
+        }
+
         *self.is_started.lock().unwrap() = false;
         *self.have_connected.lock().unwrap() = false;
+        *self.startup_retry.lock().unwrap() = false;
     }
 }
 
@@ -323,24 +403,72 @@ pub type WebSocketManagerMutex = Arc<TauriMutex<WebSocketManager>>;
 pub struct WebSocketConnection {
     pub stop_flag: Option<Arc<Mutex<bool>>>,
     pub vibin_host: String,
+    pub message_parse_errors: Arc<AtomicUsize>,
+    backoff: Backoff,
 }
 
 unsafe impl Send for WebSocketConnection {}
 
+impl Default for WebSocketConnection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WebSocketConnection {
+    pub fn new() -> Self {
+        WebSocketConnection {
+            stop_flag: None,
+            vibin_host: String::from(""),
+            message_parse_errors: Arc::new(AtomicUsize::new(0)),
+            backoff: Backoff::new(ReconnectPolicy::default()),
+        }
+    }
+
+    /// Count, log, and report a message which could not be parsed. Processing continues with the
+    /// next message.
+    fn report_parse_error(&self, description: &str, error: serde_json::Error, sink: &StateSinkRef) {
+        self.message_parse_errors.fetch_add(1, Ordering::SeqCst);
+
+        let message = format!("Could not parse {description}: {error}");
+        warn!("{message}");
+
+        sink.emit_error(&AppError {
+            category: AppErrorCategory::MessageParse,
+            message,
+        });
+    }
+
+    fn parse_payload<T: DeserializeOwned>(
+        &self,
+        vibin_msg: &VibinMessage,
+        sink: &StateSinkRef,
+    ) -> Option<T> {
+        match T::deserialize(&vibin_msg.payload) {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                self.report_parse_error(&format!("{} payload", vibin_msg.msg_type), e, sink);
+                warn!("Unparsed {} payload: {}", vibin_msg.msg_type, vibin_msg.payload);
+
+                None
+            }
+        }
+    }
+
     fn process_message(
         &self,
         vibin_msg: VibinMessage,
         vibin_state_mutex: &VibinStateMutex,
-        app_handle: AppHandle,
+        sink: StateSinkRef,
     ) {
         let mut vibin_state = vibin_state_mutex.lock().unwrap();
         let mut send_update_to_client = false;
 
         match vibin_msg.msg_type.as_str() {
             "System" => {
-                let system_payload: SystemPayload =
-                    serde_json::from_value(vibin_msg.payload).unwrap();
+                let Some(system_payload) = self.parse_payload::<SystemPayload>(&vibin_msg, &sink) else {
+                    return;
+                };
 
                 vibin_state.power = system_payload.power;
                 vibin_state.streamer_power = system_payload.streamer.power;
@@ -362,14 +490,32 @@ impl WebSocketConnection {
                 }
 
                 if let Some(sources) = system_payload.streamer.sources {
+                    // The audio format belongs to the previous source's track, so forget it until
+                    // the new source reports a CurrentlyPlaying format.
+                    let previous_source_id = vibin_state.source.as_ref().and_then(|source| source.id.clone());
+
+                    if previous_source_id != sources.active.id {
+                        vibin_state.audio_format = None;
+                    }
+
                     vibin_state.source = Some(sources.active);
+
+                    let mut selectable_sources: Vec<Source> = sources
+                        .available
+                        .into_iter()
+                        .filter(|source| source.ui_selectable.unwrap_or(false))
+                        .collect();
+
+                    selectable_sources.sort_by_key(|source| source.preferred_order.unwrap_or(isize::MAX));
+                    vibin_state.sources = selectable_sources;
                 }
 
                 send_update_to_client = true;
             }
             "TransportState" => {
-                let transport_payload: TransportStatePayload =
-                    serde_json::from_value(vibin_msg.payload).unwrap();
+                let Some(transport_payload) = self.parse_payload::<TransportStatePayload>(&vibin_msg, &sink) else {
+                    return;
+                };
 
                 vibin_state.transport = Some(TransportState {
                     play_state: transport_payload.play_state,
@@ -381,44 +527,182 @@ impl WebSocketConnection {
                 send_update_to_client = true;
             }
             "CurrentlyPlaying" => {
-                let currently_playing: CurrentlyPlayingPayload =
-                    serde_json::from_value(vibin_msg.payload).unwrap();
+                let Some(currently_playing) = self.parse_payload::<CurrentlyPlayingPayload>(&vibin_msg, &sink) else {
+                    return;
+                };
 
-                vibin_state.active_track = Some(ActiveTrack {
+                let active_track = ActiveTrack {
                     title: currently_playing.active_track.title,
                     artist: currently_playing.active_track.artist,
                     album: currently_playing.active_track.album,
                     art_url: currently_playing.active_track.art_url,
                     duration: currently_playing.active_track.duration,
-                });
+                    album_media_id: currently_playing.album_media_id,
+                    track_media_id: currently_playing.track_media_id,
+                    stream_url: currently_playing.stream.url,
+                };
+
+                let is_track_change = !vibin_state
+                    .active_track
+                    .as_ref()
+                    .is_some_and(|previous_track| previous_track.is_same_track(&active_track));
+
+                if is_track_change {
+                    sink.emit_track_change(&active_track);
+                }
+
+                vibin_state.active_track = Some(active_track);
+
+                let format = currently_playing.format;
+
+                vibin_state.audio_format = Some(
+                    AudioFormat {
+                        sample_format: format.sample_format,
+                        mqa: format.mqa,
+                        codec: format.codec,
+                        lossless: format.lossless,
+                        sample_rate: format.sample_rate,
+                        bit_depth: format.bit_depth,
+                        encoding: format.encoding,
+                        summary: None,
+                    }
+                    .with_summary(),
+                );
 
                 send_update_to_client = true;
             }
-            "Position" => {
-                let position_payload: PositionPayload =
-                    serde_json::from_value(vibin_msg.payload).unwrap();
+            "Queue" => {
+                let Some(queue_payload) = self.parse_payload::<QueuePayload>(&vibin_msg, &sink) else {
+                    return;
+                };
 
-                app_handle
-                    .emit_all(&WeeVibinMessage::Position.to_string(), Position {
-                        position: position_payload.position,
+                let entries = queue_payload
+                    .items
+                    .into_iter()
+                    .map(|item| {
+                        let metadata = item.metadata.unwrap_or(QueueItemMetadataWS {
+                            title: None,
+                            artist: None,
+                            album: None,
+                            duration: None,
+                        });
+
+                        QueueEntry {
+                            id: item.id,
+                            position: item.position,
+                            title: metadata.title,
+                            artist: metadata.artist,
+                            album: metadata.album,
+                            art_url: item.album_art_uri,
+                            duration: metadata.duration,
+                        }
                     })
-                    .unwrap();
+                    .collect();
+
+                vibin_state.queue = Some(Queue::new(entries, queue_payload.play_position));
+
+                send_update_to_client = true;
+            }
+            "Position" => {
+                let Some(position_payload) = self.parse_payload::<PositionPayload>(&vibin_msg, &sink) else {
+                    return;
+                };
+
+                sink.emit_position(&Position {
+                    position: position_payload.position,
+                });
             }
             _ => {}
         }
 
         if send_update_to_client {
-            app_handle
-                .emit_all(&WeeVibinMessage::VibinState.to_string(), &(*vibin_state))
-                .unwrap();
+            sink.emit_vibin_state(&vibin_state);
         }
     }
 
-    async fn handle_websocket(
+    fn process_text_message(
         &self,
+        message_text: &str,
+        vibin_state_mutex: &VibinStateMutex,
+        sink: StateSinkRef,
+    ) {
+        match serde_json::from_str::<VibinMessage>(message_text) {
+            Ok(vibin_msg) => self.process_message(vibin_msg, vibin_state_mutex, sink),
+            Err(e) => {
+                self.report_parse_error("WebSocket message", e, &sink);
+                warn!("Unparsed WebSocket message: {}", message_text);
+            }
+        }
+    }
+
+    /// Replay a recorded Vibin WebSocket session (see SessionRecorder) through process_message(),
+    /// as though the messages were being received from Vibin. `speed` scales the original timing
+    /// (e.g. 2.0 replays twice as fast); a `speed` of 0 replays without any delays. Returns the
+    /// number of messages replayed.
+    pub async fn replay(
+        &self,
+        recording: &Path,
+        speed: f64,
+        vibin_state_mutex: &VibinStateMutex,
+        sink: StateSinkRef,
+    ) -> Result<usize, String> {
+        let entries = read_recording(recording)
+            .map_err(|e| format!("Could not read recording {:?}: {:?}", recording, e))?;
+
+        info!("Replaying {} messages from {:?} at {speed}x", entries.len(), recording);
+
+        let started = tokio::time::Instant::now();
+        let mut replayed = 0;
+
+        for entry in entries {
+            if speed > 0.0 {
+                let offset = Duration::from_millis(entry.elapsed_ms).div_f64(speed);
+                tokio::time::sleep_until(started + offset).await;
+            }
+
+            if let RecordedMessage::Text(message_text) = entry.message {
+                self.process_text_message(&message_text, vibin_state_mutex, sink.clone());
+                replayed += 1;
+            }
+        }
+
+        info!("Replay of {:?} has completed", recording);
+
+        Ok(replayed)
+    }
+
+    fn is_stop_requested(&self) -> bool {
+        match &self.stop_flag {
+            Some(stop_flag) => *stop_flag.lock().unwrap(),
+            None => false,
+        }
+    }
+
+    /// Wait `delay` before the next connection attempt. Returns false if the stop_flag was set
+    /// while waiting, in which case no further attempts should be made.
+    async fn wait_for_retry(&self, delay: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + delay;
+
+        loop {
+            if self.is_stop_requested() {
+                return false;
+            }
+
+            let now = tokio::time::Instant::now();
+
+            if now >= deadline {
+                return true;
+            }
+
+            sleep((deadline - now).min(Duration::from_millis(250))).await;
+        }
+    }
+
+    async fn handle_websocket(
+        &mut self,
         app_state_mutex: &AppStateMutex,
         vibin_state_mutex: &VibinStateMutex,
-        app_handle: AppHandle,
+        sink: StateSinkRef,
         manager: WebSocketManager,
     ) -> Result<(), VibinWebSocketError> {
         // Don't attempt to connect if we're not Disconnected.
@@ -434,7 +718,7 @@ impl WebSocketConnection {
                         "Connection state is not Disconnected; not proceeding with Vibin WebSocket connection";
 
                     error!("{}", &err);
-                    app_handle.emit_websocket_error(&err);
+                    sink.emit_websocket_error(&err);
 
                     return Ok(());
                 },
@@ -445,23 +729,31 @@ impl WebSocketConnection {
         let url = match url::Url::parse(self.vibin_host.as_str()) {
             Ok(url) => url,
             Err(e) => {
-                app_handle.emit_websocket_error(&format!("Vibin host URL parsing error: {:?}", e));
-                return Ok(());
+                return Err(VibinWebSocketError::InvalidHostError(
+                    format!("Vibin host URL parsing error: {:?}", e)
+                ));
             }
         };
 
         {
             let mut app_state = app_state_mutex.lock().unwrap();
             app_state.vibin_connection = Connecting(self.vibin_host.clone());
-            app_handle.emit_app_state(&app_state);
+            app_state.reconnect = None;
+            sink.emit_app_state(&app_state);
         }
 
         // Detect connection attempt timeouts.
-        let connect_timeout = Duration::from_secs(5);
+        let connect_timeout = manager.connect_timeout;
         let connect_attempt = connect_async(&url);
 
         let (ws_stream, _) = match timeout(connect_timeout, connect_attempt).await {
             Ok(Ok(result)) => result,
+            Ok(Err(tungstenite::Error::Url(e))) => {
+                // The URL parsed, but isn't usable as a WebSocket URL (e.g. an http:// scheme).
+                return Err(VibinWebSocketError::InvalidHostError(
+                    format!("Invalid Vibin WebSocket URL: {}", e)
+                ));
+            }
             Ok(Err(e)) => {
                 let error_message = match e {
                     tungstenite::Error::Io(e) => format!("{}", e.to_string().replace(r#"\""#, "")),
@@ -469,13 +761,13 @@ impl WebSocketConnection {
                 };
 
                 let error = format!("Connection error: {:?}", error_message);
-                app_handle.emit_websocket_error(&error);
+                sink.emit_websocket_error(&error);
                 return Err(VibinWebSocketError::CustomError(error));
             }
             Err(_) => {
                 // Timeout
                 let error = format!("Timed out connecting to: {url}");
-                app_handle.emit_websocket_error(&error);
+                sink.emit_websocket_error(&error);
                 return Err(VibinWebSocketError::CustomError(error));
             }
         };
@@ -486,13 +778,30 @@ impl WebSocketConnection {
 
             info!("Connected to Vibin WebSocket server: {:?}",self.vibin_host);
             app_state.vibin_connection = Connected(self.vibin_host.clone());
-            app_handle.emit_app_state(&app_state);
+            sink.emit_app_state(&app_state);
         }
 
         *manager.have_connected.lock().unwrap() = true;
+        self.backoff.reset();
 
-        // This is a read-only websocket connection, so we ignore the write stream.
-        let (_, mut read) = ws_stream.split();
+        // Outgoing messages (commands from the UI, and pongs) are funneled through a channel to
+        // the write half of the stream. The manager holds the sending end while we're connected.
+        let (mut write, mut read) = ws_stream.split();
+        let (command_tx, mut command_rx) = unbounded_channel::<tungstenite::Message>();
+
+        *manager.command_sender.lock().unwrap() = Some(command_tx.clone());
+
+        let recording_dir = manager.recording_dir.lock().unwrap().clone();
+        let mut recorder = recording_dir.and_then(|dir| match SessionRecorder::create(&dir) {
+            Ok(recorder) => {
+                info!("Recording Vibin WebSocket session to {:?}", recorder.path());
+                Some(recorder)
+            }
+            Err(e) => {
+                warn!("Could not start recording Vibin WebSocket session: {:?}", e);
+                None
+            }
+        });
 
         // Read messages forever; but check at regular intervals to see if the stop_flag is set or
         // whether the client seems to have lost its connection.
@@ -538,11 +847,23 @@ impl WebSocketConnection {
                         Err(e) => error!("WebSocket error determining last ping duration: {:?}", e),
                     }
                 },
+                Some(outgoing) = command_rx.recv() => {
+                    if let Err(e) = write.send(outgoing).await {
+                        error!("Could not send message to Vibin: {:?}", e);
+                        return Err(VibinWebSocketError::WebSocketError(e));
+                    }
+                },
                 Some(next_item) = read.next() => {
                     match next_item {
                         Ok(message) => {
+                            if let Some(Err(e)) = recorder.as_mut().map(|recorder| recorder.record(&message)) {
+                                warn!("Stopping Vibin WebSocket session recording: {:?}", e);
+                                recorder = None;
+                            }
+
                             match message {
                                 tungstenite::Message::Ping(_) => {
+                                    // tungstenite responds to pings itself.
                                     let now = SystemTime::now();
 
                                     // Keep track of how long we're waiting between pings. Ignore the first
@@ -567,16 +888,8 @@ impl WebSocketConnection {
                                 },
                                 tungstenite::Message::Text(message_text) => {
                                     // Incoming VibinMessage from WebSocket server.
-                                    match serde_json::from_str::<VibinMessage>(&message_text) {
-                                        Ok(vibin_msg) => {
-                                            let app_handle = app_handle.clone();
-                                            self.process_message(vibin_msg, vibin_state_mutex, app_handle);
-                                        }
-                                        Err(e) => app_handle.emit_websocket_error(&format!(
-                                            "Could not deserialize WebSocket message; error: {:?} :: message: {}",
-                                            e, message_text
-                                        )),
-                                    }
+                                    let sink = sink.clone();
+                                    self.process_text_message(&message_text, vibin_state_mutex, sink);
                                 },
                                 unexpected => {
                                     error!("Ignoring unexpected WebSocket message type: {:?}", unexpected);
@@ -591,7 +904,7 @@ impl WebSocketConnection {
 
         let mut app_state = app_state_mutex.lock().unwrap();
         app_state.vibin_connection = Disconnected(None);
-        app_handle.emit_app_state(&app_state);
+        sink.emit_app_state(&app_state);
 
         info!("Vibin WebSocket reader has completed");
 
@@ -604,24 +917,27 @@ impl WebSocketConnection {
         stop_flag: &Arc<Mutex<bool>>,
         app_state_mutex: &AppStateMutex,
         vibin_state_mutex: &VibinStateMutex,
-        app_handle: AppHandle,
+        sink: StateSinkRef,
         manager: WebSocketManager,
     ) {
         info!("WebSocketConnection::start has been called: {vibin_host}");
         self.vibin_host = vibin_host.to_owned();
+        self.backoff = Backoff::new(manager.reconnect_policy.clone());
 
         self.stop_flag = Some(stop_flag.clone());
         *self.stop_flag.as_ref().unwrap().lock().unwrap() = false;
 
-        const RETRY_DELAY_SECS: u64 = 5;
-
         loop {
             let manager_clone = manager.clone();
 
-            match self
-                .handle_websocket(app_state_mutex, vibin_state_mutex, app_handle.clone(), manager_clone)
-                .await
-            {
+            let result = self
+                .handle_websocket(app_state_mutex, vibin_state_mutex, sink.clone(), manager_clone)
+                .await;
+
+            // The write half of the connection has gone away, so commands can no longer be sent.
+            *manager.command_sender.lock().unwrap() = None;
+
+            match result {
                 Ok(_) => {
                     info!("WebSocketConnection handle_websocket() has ended successfully");
                     app_state_mutex.lock().unwrap().set_disconnected(None);
@@ -639,22 +955,37 @@ impl WebSocketConnection {
                             app_state_mutex.lock().unwrap().set_disconnected(Some(error.clone()));
 
                             error!("WebSocketManager error: {:?}", &error);
-                            app_handle.emit_websocket_error(&error);
+                            sink.emit_websocket_error(&error);
                         }
                         _ => {
                             let error = format!("Unknown error: {:?}", e);
                             app_state_mutex.lock().unwrap().set_disconnected(Some(error.clone()));
-                            app_handle.emit_websocket_error(&error);
+                            sink.emit_websocket_error(&error);
 
                             error!("Unhandled WebSocketManager error: {:?}", error);
                         }
                     },
                     VibinWebSocketError::CustomError(e) => {
                         app_state_mutex.lock().unwrap().set_disconnected(Some(e.clone()));
-                        app_handle.emit_websocket_error(&e);
+                        sink.emit_websocket_error(&e);
 
                         error!("WebSocketManager error: {:?}", e);
                     },
+                    VibinWebSocketError::InvalidHostError(e) => {
+                        error!("WebSocketManager error: {:?}", e);
+
+                        {
+                            let mut app_state = app_state_mutex.lock().unwrap();
+                            app_state.set_disconnected(Some(e.clone()));
+                            app_state.reconnect = None;
+                            sink.emit_app_state(&app_state);
+                        }
+
+                        sink.emit_websocket_error(&e);
+
+                        // Retrying won't fix an invalid host.
+                        break;
+                    },
                     VibinWebSocketError::ClientLostConnectionError => {
                         let msg = String::from("Client lost connection to WebSocket server");
                         warn!("{msg}");
@@ -662,10 +993,10 @@ impl WebSocketConnection {
                         {
                             let mut app_state = app_state_mutex.lock().unwrap();
                             app_state.set_disconnected(Some(msg.clone()));
-                            app_handle.emit_app_state(&app_state);
+                            sink.emit_app_state(&app_state);
                         }
 
-                        app_handle.emit_websocket_error(&msg);
+                        sink.emit_websocket_error(&msg);
                     },
                     VibinWebSocketError::ServerClosedConnectionError => {
                         let msg = String::from("WebSocket server closed the connection");
@@ -674,10 +1005,10 @@ impl WebSocketConnection {
                         {
                             let mut app_state = app_state_mutex.lock().unwrap();
                             app_state.set_disconnected(Some(msg.clone()));
-                            app_handle.emit_app_state(&app_state);
+                            sink.emit_app_state(&app_state);
                         }
 
-                        app_handle.emit_websocket_error(&msg);
+                        sink.emit_websocket_error(&msg);
                     }
                 },
             }
@@ -685,16 +1016,56 @@ impl WebSocketConnection {
             // If handle_connection() exited, but we had a previously-valid connection (i.e.
             // have_connected is true) then we want to retry the connection. This is likely to
             // happen if Vibin goes offline temporarily, or if the local machine is coming back
-            // from sleep.
-            if *manager.have_connected.lock().unwrap() == true {
-                info!("Will attempt WebSocket reconnect in {RETRY_DELAY_SECS} seconds");
-                sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
-            } else {
+            // from sleep. We also retry when the manager is in startup retry mode, where the
+            // Vibin server might not be available yet.
+            if !manager.should_retry() {
                 info!("Not attempting WebSocket reconnect");
                 break;
             }
+
+            let Some(delay) = self.backoff.next_delay() else {
+                let msg = format!(
+                    "Giving up on WebSocket reconnect after {} attempts",
+                    self.backoff.attempt()
+                );
+                warn!("{msg}");
+
+                {
+                    let mut app_state = app_state_mutex.lock().unwrap();
+                    app_state.set_disconnected(Some(msg.clone()));
+                    app_state.reconnect = None;
+                    sink.emit_app_state(&app_state);
+                }
+
+                sink.emit_websocket_error(&msg);
+                break;
+            };
+
+            info!(
+                "Will attempt WebSocket reconnect #{} in {:.1} seconds",
+                self.backoff.attempt(),
+                delay.as_secs_f64()
+            );
+
+            {
+                let mut app_state = app_state_mutex.lock().unwrap();
+                app_state.set_reconnecting(self.backoff.attempt(), delay);
+                sink.emit_app_state(&app_state);
+            }
+
+            if !self.wait_for_retry(delay).await {
+                info!("WebSocket reconnect cancelled by stop request");
+
+                let mut app_state = app_state_mutex.lock().unwrap();
+                app_state.set_disconnected(None);
+                app_state.reconnect = None;
+                sink.emit_app_state(&app_state);
+                break;
+            }
         }
 
+        *self.stop_flag.as_ref().unwrap().lock().unwrap() = false;
+
         info!("WebSocketConnection start() has completed");
     }
 }
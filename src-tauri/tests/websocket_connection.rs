@@ -0,0 +1,523 @@
+// Integration tests for WebSocketManager/WebSocketConnection (and the multi-room RoomManager), run
+// against local WebSocket servers. State changes are captured with a RecordingStateSink.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Instant};
+use tokio_tungstenite::{accept_async, WebSocketStream};
+use tungstenite::Message;
+
+use weevibin::backoff::ReconnectPolicy;
+use weevibin::profiles::ServerProfile;
+use weevibin::rooms::{RecordingRoomEventSink, RoomManager};
+use weevibin::sink::{RecordingStateSink, SinkEvent};
+use weevibin::state::{AppErrorCategory, AppState, PlayState, SourceClass, VibinConnectionState, VibinState};
+use weevibin::websocket::WebSocketManager;
+
+// ------------------------------------------------------------------------------------------------
+// Helpers
+
+/// Start a WebSocket server on a random local port. Each accepted connection is passed to
+/// `handler` along with its (zero-based) connection number. Returns the server's WebSocket URL and
+/// a count of accepted connections.
+async fn spawn_server<F, Fut>(handler: F) -> (String, Arc<AtomicUsize>)
+where
+    F: Fn(WebSocketStream<TcpStream>, usize) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+
+    (url, serve(listener, handler))
+}
+
+/// Accept WebSocket connections on `listener` (see spawn_server). Returns a count of accepted
+/// connections.
+fn serve<F, Fut>(listener: TcpListener, handler: F) -> Arc<AtomicUsize>
+where
+    F: Fn(WebSocketStream<TcpStream>, usize) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let connection_count = Arc::new(AtomicUsize::new(0));
+    let count = connection_count.clone();
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let connection_number = count.fetch_add(1, Ordering::SeqCst);
+            let handler = handler.clone();
+
+            tokio::spawn(async move {
+                if let Ok(ws_stream) = accept_async(stream).await {
+                    handler(ws_stream, connection_number).await;
+                }
+            });
+        }
+    });
+
+    connection_count
+}
+
+/// Keep a server connection open, discarding anything the client sends.
+async fn hold_open(mut ws_stream: WebSocketStream<TcpStream>) {
+    while let Some(Ok(_)) = ws_stream.next().await {}
+}
+
+fn test_manager(sink: &Arc<RecordingStateSink>, vibin_host: &str) -> WebSocketManager {
+    let mut manager = WebSocketManager::new(
+        Some(Box::new(vibin_host.to_string())),
+        Arc::new(Mutex::new(false)),
+        Arc::new(Mutex::new(AppState::new())),
+        Arc::new(Mutex::new(VibinState::new())),
+        sink.clone(),
+    );
+
+    manager.reconnect_policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(50),
+        multiplier: 1.0,
+        max_delay: Duration::from_millis(50),
+        jitter: 0.0,
+        max_attempts: None,
+    };
+    manager.connect_timeout = Duration::from_millis(500);
+
+    manager
+}
+
+fn state_name(connection_state: &VibinConnectionState) -> &'static str {
+    match connection_state {
+        VibinConnectionState::Connected(_) => "Connected",
+        VibinConnectionState::Connecting(_) => "Connecting",
+        VibinConnectionState::Disconnected(_) => "Disconnected",
+        VibinConnectionState::Disconnecting => "Disconnecting",
+    }
+}
+
+/// The emitted connection states, with consecutive duplicates removed.
+fn state_names(sink: &RecordingStateSink) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = sink
+        .app_states()
+        .iter()
+        .map(|app_state| state_name(&app_state.vibin_connection))
+        .collect();
+    names.dedup();
+
+    names
+}
+
+fn was_disconnected_with(sink: &RecordingStateSink, message: &str) -> bool {
+    sink.app_states().iter().any(|app_state| {
+        app_state.vibin_connection == VibinConnectionState::Disconnected(Some(message.into()))
+    })
+}
+
+fn current_state(manager: &WebSocketManager) -> AppState {
+    manager.app_state_mutex.lock().unwrap().clone()
+}
+
+async fn wait_until(description: &str, wait: Duration, condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + wait;
+
+    while !condition() {
+        if Instant::now() > deadline {
+            panic!("Timed out waiting until {description}");
+        }
+
+        sleep(Duration::from_millis(20)).await;
+    }
+}
+
+fn is_connected(manager: &WebSocketManager) -> bool {
+    matches!(current_state(manager).vibin_connection, VibinConnectionState::Connected(_))
+}
+
+fn is_started(manager: &WebSocketManager) -> bool {
+    *manager.is_started.lock().unwrap()
+}
+
+fn vibin_message(msg_type: &str, payload: Value) -> Message {
+    Message::Text(json!({ "type": msg_type, "payload": payload }).to_string())
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_transitions_through_connection_states() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let (url, _) = spawn_server(|ws_stream, _| hold_open(ws_stream)).await;
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    wait_until("connected", Duration::from_secs(5), || is_connected(&manager)).await;
+
+    manager.stop().await;
+    wait_until("stopped", Duration::from_secs(5), || !is_started(&manager)).await;
+
+    assert_eq!(state_names(&sink), vec!["Connecting", "Connected", "Disconnecting", "Disconnected"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_reconnects_after_server_close() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let (url, connection_count) = spawn_server(|mut ws_stream, connection_number| async move {
+        if connection_number == 0 {
+            sleep(Duration::from_millis(200)).await;
+            let _ = ws_stream.send(Message::Close(None)).await;
+        }
+
+        hold_open(ws_stream).await;
+    })
+    .await;
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    wait_until("reconnected", Duration::from_secs(5), || {
+        connection_count.load(Ordering::SeqCst) == 2 && is_connected(&manager)
+    })
+    .await;
+
+    assert!(was_disconnected_with(&sink, "WebSocket server closed the connection"));
+    assert_eq!(
+        state_names(&sink),
+        vec!["Connecting", "Connected", "Disconnected", "Connecting", "Connected"]
+    );
+
+    manager.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_detects_lost_connection_when_pings_stop() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let (url, _) = spawn_server(|mut ws_stream, connection_number| async move {
+        if connection_number == 0 {
+            for _ in 0..5 {
+                let _ = ws_stream.send(Message::Ping(vec![])).await;
+                sleep(Duration::from_millis(200)).await;
+            }
+
+            // Go quiet without closing the connection.
+            sleep(Duration::from_secs(30)).await;
+        } else {
+            hold_open(ws_stream).await;
+        }
+    })
+    .await;
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    wait_until("lost connection detected", Duration::from_secs(10), || {
+        was_disconnected_with(&sink, "Client lost connection to WebSocket server")
+    })
+    .await;
+
+    manager.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_times_out_connecting_to_unresponsive_host() {
+    let sink = Arc::new(RecordingStateSink::new());
+
+    // Accept TCP connections but never complete the WebSocket handshake.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    sleep(Duration::from_millis(100)).await;
+    wait_until("stopped", Duration::from_secs(5), || !is_started(&manager)).await;
+
+    match current_state(&manager).vibin_connection {
+        VibinConnectionState::Disconnected(Some(message)) => {
+            assert!(message.starts_with("Timed out connecting to"), "{message}");
+        }
+        other => panic!("Unexpected connection state: {:?}", other),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_does_not_retry_invalid_hosts() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let mut manager = test_manager(&sink, "not a url");
+
+    manager.set_startup_retry(true);
+    manager.start();
+    sleep(Duration::from_millis(100)).await;
+    wait_until("stopped", Duration::from_secs(5), || !is_started(&manager)).await;
+
+    let app_state = current_state(&manager);
+
+    assert!(app_state.reconnect.is_none());
+    assert!(matches!(
+        app_state.vibin_connection,
+        VibinConnectionState::Disconnected(Some(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_retries_an_unreachable_host_at_startup_until_it_comes_up() {
+    let sink = Arc::new(RecordingStateSink::new());
+
+    // Find a free port, and leave nothing listening on it (yet).
+    let address = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let mut manager = test_manager(&sink, &format!("ws://{address}/ws"));
+
+    manager.set_startup_retry(true);
+    manager.start();
+    wait_until("first attempt failed", Duration::from_secs(5), || {
+        current_state(&manager).reconnect.is_some()
+    })
+    .await;
+
+    assert!(is_started(&manager));
+    assert!(!*manager.have_connected.lock().unwrap());
+
+    // The host comes up.
+    let connection_count = serve(TcpListener::bind(address).await.unwrap(), |ws_stream, _| {
+        hold_open(ws_stream)
+    });
+    wait_until("connected", Duration::from_secs(5), || is_connected(&manager)).await;
+
+    assert_eq!(connection_count.load(Ordering::SeqCst), 1);
+    assert!(current_state(&manager).reconnect.is_none());
+
+    manager.stop().await;
+
+    assert!(!*manager.startup_retry.lock().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_builds_vibin_state_from_messages() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let (url, _) = spawn_server(|mut ws_stream, _| async move {
+        let messages = vec![
+            vibin_message("System", json!({
+                "power": "on",
+                "streamer": {
+                    "power": "on",
+                    "sources": {
+                        "active": { "name": "Media Library", "class": "stream.media" },
+                        "available": [
+                            { "id": "AIRPLAY", "name": "AirPlay", "ui_selectable": true, "preferred_order": 2 },
+                            { "id": "SPDIF", "name": "S/PDIF", "ui_selectable": false, "preferred_order": 0 },
+                            { "id": "MEDIA_PLAYER", "name": "Media Library", "ui_selectable": true, "preferred_order": 1 },
+                        ],
+                    },
+                    "display": { "line1": "Teardrop", "format": "FLAC 24/96" },
+                },
+                "amplifier": { "mute": "off", "volume": 0.4 },
+            })),
+            vibin_message("TransportState", json!({
+                "play_state": "play",
+                "active_controls": ["pause", "next"],
+                "repeat": "off",
+                "shuffle": "all",
+            })),
+            vibin_message("CurrentlyPlaying", json!({
+                "album_media_id": "album-1",
+                "track_media_id": "track-1",
+                "active_track": {
+                    "title": "Teardrop",
+                    "artist": "Massive Attack",
+                    "album": "Mezzanine",
+                    "duration": 330,
+                },
+                "format": { "codec": "FLAC", "sample_rate": 96000, "bit_depth": 24 },
+                "stream": { "url": null },
+            })),
+            vibin_message("Queue", json!({
+                "play_position": 0,
+                "items": [
+                    { "id": 7, "position": 0, "metadata": { "title": "Teardrop", "duration": 330 } },
+                    { "id": 8, "position": 1, "metadata": { "title": "Angel", "duration": 379 } },
+                ],
+            })),
+            vibin_message("Position", json!({ "position": 42 })),
+        ];
+
+        for message in messages {
+            ws_stream.send(message).await.unwrap();
+        }
+
+        hold_open(ws_stream).await;
+    })
+    .await;
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    wait_until("position received", Duration::from_secs(5), || {
+        sink.positions().iter().any(|position| position.position == 42)
+    })
+    .await;
+
+    {
+        let vibin_state = manager.vibin_state_mutex.lock().unwrap();
+        let active_track = vibin_state.active_track.as_ref().unwrap();
+        let transport = vibin_state.transport.as_ref().unwrap();
+
+        assert_eq!(active_track.title.as_deref(), Some("Teardrop"));
+        assert_eq!(active_track.artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(active_track.duration, Some(330));
+        assert_eq!(active_track.track_media_id.as_deref(), Some("track-1"));
+        assert_eq!(sink.track_changes().len(), 1);
+        assert_eq!(
+            vibin_state.audio_format.as_ref().unwrap().summary.as_deref(),
+            Some("FLAC 24/96")
+        );
+        assert_eq!(transport.play_state, Some(PlayState::Play));
+        assert_eq!(transport.active_controls, vec!["pause", "next"]);
+        assert!(vibin_state.is_playing());
+        assert_eq!(vibin_state.display.line1.as_deref(), Some("Teardrop"));
+        assert_eq!(vibin_state.amplifier.as_ref().unwrap().volume, Some(0.4));
+        assert_eq!(vibin_state.source.as_ref().unwrap().name.as_deref(), Some("Media Library"));
+        assert_eq!(vibin_state.source.as_ref().unwrap().class, Some(SourceClass::StreamMedia));
+        assert_eq!(
+            vibin_state.sources.iter().map(|source| source.id.as_deref().unwrap()).collect::<Vec<_>>(),
+            vec!["MEDIA_PLAYER", "AIRPLAY"]
+        );
+
+        let queue = vibin_state.queue.as_ref().unwrap();
+
+        assert_eq!(queue.entries.len(), 2);
+        assert_eq!(queue.current_entry().map(|entry| entry.id), Some(7));
+        assert_eq!(queue.total_duration, 709);
+    }
+
+    manager.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_continues_after_unparseable_messages() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let (url, _) = spawn_server(|mut ws_stream, _| async move {
+        let messages = vec![
+            Message::Text("not json".into()),
+            vibin_message("TransportState", json!({ "play_state": 12 })),
+            vibin_message("Position", json!({ "position": 7 })),
+        ];
+
+        for message in messages {
+            ws_stream.send(message).await.unwrap();
+        }
+
+        hold_open(ws_stream).await;
+    })
+    .await;
+    let mut manager = test_manager(&sink, &url);
+
+    manager.start();
+    wait_until("position received", Duration::from_secs(5), || {
+        sink.positions().iter().any(|position| position.position == 7)
+    })
+    .await;
+
+    let errors = sink.errors();
+
+    assert_eq!(manager.message_parse_error_count(), 2);
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|error| error.category == AppErrorCategory::MessageParse));
+    assert!(is_connected(&manager));
+
+    manager.stop().await;
+}
+
+/// Emit the given position once every 50ms, as though a track was playing.
+async fn send_positions(mut ws_stream: WebSocketStream<TcpStream>, position: isize) {
+    loop {
+        let message = vibin_message("Position", json!({ "position": position }));
+
+        if ws_stream.send(message).await.is_err() {
+            return;
+        }
+
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_connects_to_multiple_rooms_and_follows_the_selected_room() {
+    let sink = Arc::new(RecordingStateSink::new());
+    let room_events = Arc::new(RecordingRoomEventSink::new());
+    let (lounge_url, _) = spawn_server(|ws_stream, _| send_positions(ws_stream, 1)).await;
+    let (study_url, _) = spawn_server(|ws_stream, _| send_positions(ws_stream, 2)).await;
+
+    let app_state = Arc::new(Mutex::new(AppState::new()));
+    let mut rooms = RoomManager::new(
+        sink.clone(),
+        app_state.clone(),
+        Arc::new(Mutex::new(VibinState::new())),
+        room_events.clone(),
+    );
+
+    let room_positions = |room_id: &str| -> Vec<isize> {
+        room_events
+            .events_for(room_id)
+            .into_iter()
+            .filter_map(|event| match event {
+                SinkEvent::Position(position) => Some(position.position),
+                _ => None,
+            })
+            .collect()
+    };
+
+    rooms
+        .sync_rooms(&[
+            ServerProfile { name: "Lounge".into(), host: lounge_url },
+            ServerProfile { name: "Study".into(), host: study_url },
+        ])
+        .await;
+
+    wait_until("both rooms receive positions", Duration::from_secs(5), || {
+        !room_positions("Lounge").is_empty() && !room_positions("Study").is_empty()
+    })
+    .await;
+
+    // Each room's events are tagged with the right room, and nothing reaches the global sink until
+    // a room is selected.
+    assert!(room_positions("Lounge").iter().all(|position| *position == 1));
+    assert!(room_positions("Study").iter().all(|position| *position == 2));
+    assert!(sink.positions().is_empty());
+    assert!(rooms.select_room("Kitchen").is_err());
+
+    rooms.select_room("Study").unwrap();
+    wait_until("selected room positions", Duration::from_secs(5), || {
+        !sink.positions().is_empty()
+    })
+    .await;
+
+    assert!(sink.positions().iter().all(|position| position.position == 2));
+    assert!(matches!(
+        app_state.lock().unwrap().vibin_connection,
+        VibinConnectionState::Connected(_)
+    ));
+
+    rooms.select_room("Lounge").unwrap();
+    wait_until("newly selected room positions", Duration::from_secs(5), || {
+        sink.positions().last().map(|position| position.position) == Some(1)
+    })
+    .await;
+
+    let summaries = rooms.rooms();
+
+    assert_eq!(summaries.len(), 2);
+    assert!(summaries.iter().all(|room| state_name(&room.vibin_connection) == "Connected"));
+    assert_eq!(rooms.selected_room().as_deref(), Some("Lounge"));
+
+    rooms.stop_all().await;
+
+    assert!(!rooms.is_active());
+    assert_eq!(rooms.selected_room(), None);
+}
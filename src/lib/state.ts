@@ -1,12 +1,14 @@
-import { type Updater, derived, writable } from "svelte/store";
+import { type Updater, derived, get, writable } from "svelte/store";
 import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/tauri";
 
 import type {
     ActiveTrack,
     Amplifier,
+    AudioFormat,
     Position,
     Power,
+    Queue,
     Source,
     SourceClass,
     StreamerDisplay,
@@ -20,26 +22,158 @@ import {
 } from "./persisted_state.ts";
 
 // UI application screens
-type Screen = "main" | "settings";
+type Screen = "main" | "queue" | "settings" | "shortcuts" | "scrobbling" | "stats";
 
 // State of the Rust WebSocket connection to the Vibin backend
 export type ConnectionStatus = "Connected" | "Connecting" | "Disconnected" | "Disconnecting";
 
+// Details of the next WebSocket reconnect attempt (retry_at is milliseconds since the epoch)
+export type ReconnectStatus = {
+    attempt: number;
+    delay_secs: number;
+    retry_at: number;
+};
+
 // weevibin application state
 type AppState = {
     vibin_connection: {
         state: ConnectionStatus;
         message?: string;
     };
+    reconnect?: ReconnectStatus;
 };
 
-type AppErrorCategory = "WebSocket";
+type AppErrorCategory = "WebSocket" | "VibinApi" | "MessageParse" | "Shortcut";
 
 type AppError = {
     category: AppErrorCategory;
     message: string;
 };
 
+// Named Vibin servers (e.g. one per room). `host` is a Vibin WebSocket URL.
+export type ServerProfile = {
+    name: string;
+    host: string;
+};
+
+export type ServerProfiles = {
+    profiles: ServerProfile[];
+    active?: string;
+};
+
+// Global shortcuts. `accelerator` is a Tauri accelerator, e.g. "CmdOrCtrl+Alt+Up".
+export type ShortcutAction = "play_pause" | "next" | "previous" | "volume_up" | "volume_down" | "mute";
+
+export type ShortcutBindings = {
+    bindings: {
+        action: ShortcutAction;
+        accelerator?: string;
+    }[];
+};
+
+// Track change notifications. `muted_source_classes` are source classes (e.g. "stream.radio")
+// which never notify.
+export type NotificationSettings = {
+    enabled: boolean;
+    muted_source_classes: string[];
+};
+
+// Scrobbling to a ListenBrainz-compatible server. `api_url` is the API root, e.g.
+// "https://api.listenbrainz.org".
+export type ScrobbleSettings = {
+    enabled: boolean;
+    api_url: string;
+    token: string;
+};
+
+// The local HTTP control API, served on localhost at `port` when enabled. Requests need an
+// "Authorization: Bearer <token>" header.
+export type ControlApiSettings = {
+    enabled: boolean;
+    port: number;
+    token: string;
+};
+
+// The listening history, most recent first (see getListeningHistory). `started_at` is in seconds
+// since the epoch.
+export type HistoryEntry = {
+    id: number;
+    title?: string;
+    artist?: string;
+    album?: string;
+    album_media_id?: string;
+    track_media_id?: string;
+    source_class?: string;
+    audio_format?: string;
+    sample_rate?: number;
+    bit_depth?: number;
+    lossless?: boolean;
+    started_at: number;
+    duration?: number;
+    listened_secs: number;
+    skipped: boolean;
+};
+
+export type HistoryPage = {
+    entries: HistoryEntry[];
+    total: number;
+};
+
+// Listening stats (see getListeningStats). Top lists count listens (plays which weren't skipped).
+export type StatsPeriod = "day" | "week" | "month" | "year" | "all_time";
+
+export type TopEntry = {
+    artist?: string;
+    album?: string;
+    title?: string;
+    listens: number;
+    listened_secs: number;
+};
+
+// `name` is a source class (e.g. "stream.media") or audio quality ("hi_res", "cd", "lossy"), or
+// "unknown".
+export type Breakdown = {
+    name: string;
+    plays: number;
+    listened_secs: number;
+};
+
+export type ListeningStats = {
+    period: StatsPeriod;
+    from: number;
+    to: number;
+    plays: number;
+    skips: number;
+    listened_secs: number;
+    top_artists: TopEntry[];
+    top_albums: TopEntry[];
+    top_tracks: TopEntry[];
+    daily: { date: string; listened_secs: number }[];
+    weekly: { date: string; listened_secs: number }[];
+    by_source_class: Breakdown[];
+    by_audio_quality: Breakdown[];
+};
+
+// Multi-room mode rooms (one per server profile). `id` is the profile name.
+export type Room = {
+    id: string;
+    host: string;
+    vibin_connection: AppState["vibin_connection"];
+    selected: boolean;
+};
+
+// A message from a single room's connection (multi-room mode). The main UI follows the selected
+// room via the usual (untagged) messages; these are for anything showing every room.
+type RoomEvent = {
+    room_id: string;
+    event:
+        | { type: "AppState"; payload: AppState }
+        | { type: "VibinState"; payload: VibinState }
+        | { type: "Position"; payload: Position }
+        | { type: "TrackChange"; payload: ActiveTrack }
+        | { type: "Error"; payload: AppError };
+};
+
 // All known state information from the Vibin backend
 export type VibinState = {
     power?: Power,
@@ -48,7 +182,10 @@ export type VibinState = {
     display: StreamerDisplay,
     transport?: Transport,
     source?: Source,
+    sources?: Source[],
     active_track?: ActiveTrack,
+    audio_format?: AudioFormat,
+    queue?: Queue,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -74,6 +211,21 @@ export let vibinState = writable<VibinState>(DEFAULT_VIBIN_STATE);
 
 export let playheadPosition = writable<number | undefined>(undefined);
 
+export let serverProfiles = writable<ServerProfiles>({ profiles: [] });
+
+export let shortcutBindings = writable<ShortcutBindings>({ bindings: [] });
+
+export let notificationSettings = writable<NotificationSettings>({ enabled: false, muted_source_classes: [] });
+
+export let scrobbleSettings = writable<ScrobbleSettings>({ enabled: false, api_url: "", token: "" });
+
+export let controlApiSettings = writable<ControlApiSettings>({ enabled: false, port: 7681, token: "" });
+
+export let rooms = writable<Room[]>([]);
+
+// Each room's latest VibinState, keyed by room id (multi-room mode only).
+export let roomVibinStates = writable<Record<string, VibinState>>({});
+
 /**
  * Create a Svelte writable which wraps the persisted Vibin host details.
  */
@@ -102,6 +254,8 @@ export const vibinHost = await createVibinHostState();
 export const isSystemPowerOn = derived(vibinState, ($vibinState) => $vibinState.power === "on");
 export const isStreamerPowerOn = derived(vibinState, ($vibinState) => $vibinState.streamer_power === "on");
 
+export const isMultiRoom = derived(rooms, ($rooms) => $rooms.length > 0);
+
 export const isConnected = derived(appState, ($appState) => $appState.vibin_connection.state === "Connected");
 
 export const isPlaying = derived(vibinState, ($vibinState) => $vibinState.transport?.play_state === "play");
@@ -139,12 +293,12 @@ const initialize = async () => {
             // Vibin state to ensure the UI enters a "no information known" state.
             vibinState.set(DEFAULT_VIBIN_STATE);
 
-            // If we're disconnected from the Vibin WebSocket server with an error message,
-            // then we want to persist the fact that we haven't connected successfully. This
-            // can be used to drive how the UI behaves on startup/etc.
+            // If we're disconnected from the Vibin WebSocket server with an error message (and
+            // Rust isn't going to retry), then we want to persist the fact that we haven't
+            // connected successfully. This can be used to drive how the UI behaves on startup/etc.
             const connInfo = message.payload.vibin_connection;
 
-            if (connInfo.state === "Disconnected" && connInfo.message) {
+            if (connInfo.state === "Disconnected" && connInfo.message && !message.payload.reconnect) {
                 vibinHost.setHaveConnected(false);
             }
         }
@@ -178,6 +332,50 @@ const initialize = async () => {
         appErrorState.set(message.payload);
     });
 
+    // Switching profiles (from the UI or the tray) changes the Vibin host. The newly-active
+    // profile's host is persisted as the Vibin host so it's reconnected to at startup.
+    await listen<ServerProfiles>("ServerProfiles", async (message) => {
+        const priorActive = get(serverProfiles).active;
+        const { profiles, active } = message.payload;
+
+        serverProfiles.set(message.payload);
+
+        const activeProfile = profiles.find((profile) => profile.name === active);
+
+        if (activeProfile && active !== priorActive) {
+            await vibinHost.setHostName(activeProfile.host);
+        }
+    });
+
+    await listen<Room[]>("Rooms", (message) => {
+        rooms.set(message.payload);
+
+        const roomIds = message.payload.map((room) => room.id);
+
+        roomVibinStates.update((states) =>
+            Object.fromEntries(Object.entries(states).filter(([roomId]) => roomIds.includes(roomId)))
+        );
+    });
+
+    await listen<RoomEvent>("RoomEvent", (message) => {
+        const { room_id, event } = message.payload;
+
+        if (event.type === "AppState") {
+            rooms.update((priorRooms) => priorRooms.map((room) =>
+                room.id === room_id ? { ...room, vibin_connection: event.payload.vibin_connection } : room
+            ));
+        } else if (event.type === "VibinState") {
+            roomVibinStates.update((states) => ({ ...states, [room_id]: event.payload }));
+        }
+    });
+
+    serverProfiles.set(await invoke<ServerProfiles>("get_server_profiles"));
+    rooms.set(await invoke<Room[]>("get_rooms"));
+    shortcutBindings.set(await invoke<ShortcutBindings>("get_shortcut_bindings"));
+    notificationSettings.set(await invoke<NotificationSettings>("get_notification_settings"));
+    scrobbleSettings.set(await invoke<ScrobbleSettings>("get_scrobble_settings"));
+    controlApiSettings.set(await invoke<ControlApiSettings>("get_control_api_settings"));
+
     // Inform Rust that the UI is ready. This means that the message listeners are all primed.
     await invoke("on_ui_ready");
 
@@ -3,6 +3,16 @@ import { fetch, ResponseType } from "@tauri-apps/api/http";
 import * as logger from "tauri-plugin-log-api";
 
 import { DEFAULT_VIBIN_PORT } from "./consts.ts";
+import type {
+    ControlApiSettings,
+    HistoryPage,
+    ListeningStats,
+    NotificationSettings,
+    ScrobbleSettings,
+    ShortcutAction,
+    ShortcutBindings,
+    StatsPeriod,
+} from "./state.ts";
 
 /**
  * Request (from Rust) a connection to the Vibin WebSocket server at `host`.
@@ -10,15 +20,86 @@ import { DEFAULT_VIBIN_PORT } from "./consts.ts";
  * This only gets as far as invoking set_vibin_server, which will return _before_ the connection
  * attempt completes on the Rust side. This means that the success/failure of the connection
  * attempt will not be known until later (via AppState.vibin_connection.state).
+ *
+ * If `startupRetry` is true, then Rust will keep retrying the connection (with backoff) even if
+ * the host has not yet been connected to. This is used for the startup connection attempt.
+ */
+const connectToVibin = async (host: string, startupRetry: boolean = false) => {
+    await invoke("set_vibin_server", { vibinServer: vibinWebSocketUrl(host), startupRetry });
+}
+
+/**
+ * Convert a Vibin host (e.g. "vibin.local") into a Vibin WebSocket URL, adding the default scheme,
+ * port, and path where required.
  */
-const connectToVibin = async (host: string) => {
+const vibinWebSocketUrl = (host: string): string => {
     const wsUrl = new URL(`${/^wss?:\/\//.test(host) ? "" : "ws://"}${host}`);
     wsUrl.port = wsUrl.port ? wsUrl.port : `${DEFAULT_VIBIN_PORT}`;
     wsUrl.pathname = wsUrl.pathname === "/" ? "/ws" : wsUrl.pathname;
 
-    await invoke("set_vibin_server", { vibinServer: wsUrl });
+    return wsUrl.toString();
 }
 
+/**
+ * Save `host` as the named Vibin server profile (replacing any existing profile with that name).
+ */
+const saveServerProfile = async (name: string, host: string) =>
+    await invoke("save_server_profile", { name, host: vibinWebSocketUrl(host) });
+
+const deleteServerProfile = async (name: string) => await invoke("delete_server_profile", { name });
+
+/**
+ * Make the named profile the active one. Rust disconnects from the current Vibin server and
+ * connects to the profile's server (or, in multi-room mode, selects the profile's room).
+ */
+const switchServerProfile = async (name: string) => await invoke("switch_server_profile", { name });
+
+/**
+ * Enable or disable multi-room mode. When enabled, Rust connects to every server profile's Vibin
+ * server at once; the controls target the selected room (switch rooms with switchServerProfile).
+ */
+const setMultiRoomMode = async (enabled: boolean) => await invoke("set_multi_room_mode", { enabled });
+
+/**
+ * Bind a global shortcut action to a Tauri accelerator (or unbind it, when `accelerator` is
+ * undefined). Rejects if the accelerator is already in use. Resolves to the updated bindings.
+ */
+const setShortcutBinding = async (action: ShortcutAction, accelerator: string | undefined) =>
+    await invoke<ShortcutBindings>("set_shortcut_binding", { action, accelerator });
+
+/**
+ * Persist the track change notification settings. Resolves to the updated settings.
+ */
+const setNotificationSettings = async (notificationSettings: NotificationSettings) =>
+    await invoke<NotificationSettings>("set_notification_settings", { notificationSettings });
+
+/**
+ * Persist the scrobble settings. Resolves to the updated settings.
+ */
+const setScrobbleSettings = async (scrobbleSettings: ScrobbleSettings) =>
+    await invoke<ScrobbleSettings>("set_scrobble_settings", { scrobbleSettings });
+
+/**
+ * Persist the control API settings, (re)starting or stopping the server to match. Rejects (leaving
+ * the previous settings in place) if the server can't listen on the port. Resolves to the updated
+ * settings.
+ */
+const setControlApiSettings = async (controlApiSettings: ControlApiSettings) =>
+    await invoke<ControlApiSettings>("set_control_api_settings", { controlApiSettings });
+
+/**
+ * Get a page of the listening history, most recent first. With a `search`, only plays whose
+ * title, artist, or album contain it are included.
+ */
+const getListeningHistory = async (offset: number, limit: number, search?: string) =>
+    await invoke<HistoryPage>("get_listening_history", { offset, limit, search });
+
+/**
+ * Get the listening stats for the period ending now. `topLimit` is the length of each top list.
+ */
+const getListeningStats = async (period: StatsPeriod, topLimit?: number) =>
+    await invoke<ListeningStats>("get_listening_stats", { period, topLimit });
+
 /**
  * Get the color value for the given cssVarName.
  *
@@ -55,10 +136,31 @@ const isUrlOk = async (url: string, responseType: ResponseType = ResponseType.Bi
     return isOk;
 }
 
+const leadingZeros = new RegExp("^00:");
+
+/**
+ * Convert a duration in seconds into "hh:mm:ss", without the hh: if it would have been "00:".
+ */
+const prettyDuration = (duration: number | undefined) =>
+    typeof duration === "number" ?
+        new Date(duration * 1000).toISOString().substring(11, 19).replace(leadingZeros, "")
+        : "--:--";
+
 export {
     colorFromCssVar,
     connectToVibin,
+    deleteServerProfile,
+    getListeningHistory,
+    getListeningStats,
     isUrlOk,
     logger,
+    prettyDuration,
+    saveServerProfile,
+    setControlApiSettings,
+    setMultiRoomMode,
+    setNotificationSettings,
+    setScrobbleSettings,
+    setShortcutBinding,
+    switchServerProfile,
 };
 
@@ -1,49 +1,54 @@
-import { DEFAULT_VIBIN_PORT } from "./consts.ts";
-import { vibinHost } from "./state.ts";
+import { invoke } from "@tauri-apps/api/tauri";
 
-let host: string | null = null;
+import type { TransportAction } from "./vibin_types.ts";
 
-vibinHost.subscribe((hostDetails) => {
-    const hostName = hostDetails.host;
+// Vibin commands are sent by Rust, which reports any failures back to the UI as "Error" messages.
+// Failures are therefore swallowed here to keep the callers (button handlers, etc) simple.
 
-    if (/:[0-9]+$/.test(hostName)) {
-        // Port override already specified
-        host = `http://${hostName}`;
-    } else {
-        host = `http://${hostName}:${DEFAULT_VIBIN_PORT}`;
+const invokeVibinCommand = async (command: string, args?: Record<string, unknown>) => {
+    try {
+        await invoke(command, args);
+    } catch (e) {
+        // Already reported via the "Error" message channel.
     }
-});
+}
 
-export const sendVibinCommand = async (endpoint: string) => {
-    if (!host) {
-        return;
-    }
+/**
+ * Send a command to Vibin over Rust's existing WebSocket connection.
+ */
+export const sendVibinWebSocketCommand = async (command: string, payload?: object) =>
+    await invoke("send_vibin_command", { command, payload });
 
-    const response = await fetch(
-        `${host}/api${endpoint}`,
-        {
-            method: "POST",
-        }
-    );
-}
+const transport = async (action: TransportAction) =>
+    await invokeVibinCommand("vibin_transport", { action });
 
 // System
-export const powerOn = async () => await sendVibinCommand("/system/power/on");
-export const powerOff = async () => await sendVibinCommand("/system/power/off");
+export const powerOn = async () => await invokeVibinCommand("vibin_power", { action: "on" });
+export const powerOff = async () => await invokeVibinCommand("vibin_power", { action: "off" });
+export const setSource = async (sourceId: string) => await invokeVibinCommand("vibin_set_source", { sourceId });
 
 // Transport
-export const togglePlayback = async () => await sendVibinCommand("/transport/toggle_playback");
-export const nextTrack = async () => await sendVibinCommand("/transport/next");
-export const pause = async () => await sendVibinCommand("/transport/pause");
-export const play = async () => await sendVibinCommand("/transport/play");
-export const previousTrack = async () => await sendVibinCommand("/transport/previous");
-export const stop = async () => await sendVibinCommand("/transport/stop");
-export const toggleRepeat = async () => await sendVibinCommand("/transport/repeat");
-export const toggleShuffle = async () => await sendVibinCommand("/transport/shuffle");
-export const seek = async (target: number) => await sendVibinCommand(`/transport/seek?target=${target}`);
+export const togglePlayback = async () => await transport("toggle_playback");
+export const nextTrack = async () => await transport("next");
+export const pause = async () => await transport("pause");
+export const play = async () => await transport("play");
+export const previousTrack = async () => await transport("previous");
+export const stop = async () => await transport("stop");
+export const toggleRepeat = async () => await transport("repeat");
+export const toggleShuffle = async () => await transport("shuffle");
+export const seek = async (target: number) => await invokeVibinCommand("vibin_seek", { target });
+
+// Queue
+export const queuePlay = async (entryId: number) => await invokeVibinCommand("vibin_queue_play", { entryId });
+export const queueDelete = async (entryId: number) => await invokeVibinCommand("vibin_queue_delete", { entryId });
+export const queueMove = async (entryId: number, fromPosition: number, toPosition: number) =>
+    await invokeVibinCommand("vibin_queue_move", { entryId, fromPosition, toPosition });
+
+// vibinui
+export const openInVibinUI = async () => await invokeVibinCommand("open_in_vibinui");
 
 // Amplifier
-export const toggleMute = async () => await sendVibinCommand("/system/amplifier/mute/toggle");
-export const volumeUp = async () => await sendVibinCommand("/system/amplifier/volume/up");
-export const volumeDown = async () => await sendVibinCommand("/system/amplifier/volume/down");
-export const volumeSet = async (level: number) => await sendVibinCommand(`/system/amplifier/volume/${level}`);
+export const toggleMute = async () => await invokeVibinCommand("vibin_toggle_mute");
+export const volumeUp = async () => await invokeVibinCommand("vibin_volume_up");
+export const volumeDown = async () => await invokeVibinCommand("vibin_volume_down");
+export const volumeSet = async (level: number) => await invokeVibinCommand("vibin_volume_set", { level });
@@ -58,6 +58,20 @@ export type ActiveTrack = {
     album: string;
     art_url: string;
     duration: number;
+    album_media_id?: string;
+    track_media_id?: string;
+    stream_url?: string;
+}
+
+export type AudioFormat = {
+    sample_format?: string,
+    mqa?: string,
+    codec?: string,
+    lossless?: boolean,
+    sample_rate?: number,
+    bit_depth?: number,
+    encoding?: string,
+    summary?: string,
 }
 
 export type Amplifier = {
@@ -84,3 +98,19 @@ export type Transport = {
 export type Position = {
     position: number,
 }
+
+export type QueueEntry = {
+    id: number,
+    position: number,
+    title?: string,
+    artist?: string,
+    album?: string,
+    art_url?: string,
+    duration?: number,
+}
+
+export type Queue = {
+    entries: QueueEntry[],
+    current_index?: number,
+    total_duration: number,
+}